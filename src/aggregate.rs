@@ -0,0 +1,392 @@
+/*
+ * Time Aggregation
+ *
+ * Aggregation of logger time series into hours, days, weeks, months, years
+ * or custom seasonal windows, following the myClim R package function
+ * `mc_agg`.
+ * Original myClim package: https://github.com/ibot-geoecology/myClim
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+#![allow(clippy::cast_precision_loss)]
+
+use crate::clean::{detect_step, TimeSeriesRecord};
+use anyhow::{bail, Result};
+use chrono::{Datelike, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Timelike};
+use serde::{Deserialize, Serialize};
+
+/// Aggregation period.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Period {
+    Hour,
+    Day,
+    /// ISO week, starting on Monday
+    Week,
+    Month,
+    Year,
+    /// The whole series
+    All,
+    /// Seasonal window repeated every year, `(month, day)` of the first and
+    /// last day (inclusive). Windows may span the new year.
+    Custom {
+        start: (u32, u32),
+        end: (u32, u32),
+    },
+}
+
+impl Period {
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Period::Hour => "hour",
+            Period::Day => "day",
+            Period::Week => "week",
+            Period::Month => "month",
+            Period::Year => "year",
+            Period::All => "all",
+            Period::Custom { .. } => "custom",
+        }
+    }
+
+    /// Start and (exclusive) end of the period containing `datetime`, `None`
+    /// if a custom window does not contain it.
+    #[must_use]
+    pub fn bounds(&self, datetime: NaiveDateTime) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        let date = datetime.date();
+        let day_start = |d: NaiveDate| d.and_time(NaiveTime::MIN);
+        match *self {
+            Period::Hour => {
+                let start = day_start(date) + TimeDelta::hours(i64::from(datetime.hour()));
+                Some((start, start + TimeDelta::hours(1)))
+            }
+            Period::Day => Some((day_start(date), day_start(date) + TimeDelta::days(1))),
+            Period::Week => {
+                let monday = date.week(chrono::Weekday::Mon).first_day();
+                Some((day_start(monday), day_start(monday) + TimeDelta::weeks(1)))
+            }
+            Period::Month => {
+                let first = date.with_day(1)?;
+                Some((day_start(first), day_start(first + Months::new(1))))
+            }
+            Period::Year => {
+                let first = NaiveDate::from_ymd_opt(date.year(), 1, 1)?;
+                Some((day_start(first), day_start(first + Months::new(12))))
+            }
+            Period::All => None,
+            Period::Custom { start, end } => {
+                let md = (date.month(), date.day());
+                let wraps = start > end;
+                let year = if wraps && md <= end {
+                    date.year() - 1
+                } else {
+                    date.year()
+                };
+                let from = month_day_date(year, start)?;
+                let to_year = if wraps { year + 1 } else { year };
+                let to = month_day_date(to_year, end)?.succ_opt()?;
+                (date >= from && date < to).then(|| (day_start(from), day_start(to)))
+            }
+        }
+    }
+}
+
+/// Date of `month_day` in `year`, 02-29 falling back to 02-28 in common
+/// years so that a window bound on 02-29 ends on 03-01 (exclusive).
+fn month_day_date(year: i32, (month, day): (u32, u32)) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(year, month, day).or_else(|| {
+        if (month, day) == (2, 29) {
+            NaiveDate::from_ymd_opt(year, 2, 28)
+        } else {
+            None
+        }
+    })
+}
+
+fn parse_month_day(s: &str) -> Option<(u32, u32)> {
+    let (m, d) = s.split_once('-')?;
+    let md = (m.parse().ok()?, d.parse().ok()?);
+    // Validate against a leap year so that 02-29 is accepted
+    NaiveDate::from_ymd_opt(2000, md.0, md.1).map(|_| md)
+}
+
+impl TryFrom<&str> for Period {
+    type Error = String;
+
+    /// `hour`, `day`, `week`, `month`, `year`, `all` or
+    /// `custom:<MM-DD>:<MM-DD>` (e.g. `custom:05-01:09-30`).
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s.to_lowercase().as_str() {
+            "hour" => Ok(Period::Hour),
+            "day" => Ok(Period::Day),
+            "week" => Ok(Period::Week),
+            "month" => Ok(Period::Month),
+            "year" => Ok(Period::Year),
+            "all" => Ok(Period::All),
+            other => {
+                let mut parts = other.split(':');
+                match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                    (Some("custom"), Some(start), Some(end), None) => {
+                        match (parse_month_day(start), parse_month_day(end)) {
+                            (Some(start), Some(end)) => Ok(Period::Custom { start, end }),
+                            _ => Err(format!("Invalid custom period: {s}")),
+                        }
+                    }
+                    _ => Err(format!("Unknown aggregation period: {s}")),
+                }
+            }
+        }
+    }
+}
+
+/// Function applied to the values of each period.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AggFunction {
+    Min,
+    Max,
+    Mean,
+    Sum,
+    /// Max - min
+    Range,
+    /// Number of non-missing values
+    Count,
+    /// Fraction of the expected records present in the period
+    Coverage,
+    /// Percentile (0 to 100) with linear interpolation (R `quantile` type 7)
+    Percentile(f64),
+}
+
+impl AggFunction {
+    /// Column suffix, e.g. `mean` or `percentile95`.
+    #[must_use]
+    pub fn name(&self) -> String {
+        match self {
+            AggFunction::Min => "min".to_string(),
+            AggFunction::Max => "max".to_string(),
+            AggFunction::Mean => "mean".to_string(),
+            AggFunction::Sum => "sum".to_string(),
+            AggFunction::Range => "range".to_string(),
+            AggFunction::Count => "count".to_string(),
+            AggFunction::Coverage => "coverage".to_string(),
+            AggFunction::Percentile(p) => format!("percentile{p}"),
+        }
+    }
+}
+
+impl TryFrom<&str> for AggFunction {
+    type Error = String;
+
+    /// `min`, `max`, `mean`, `sum`, `range`, `count`, `coverage` or
+    /// `percentile<p>` (e.g. `percentile95`).
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s.to_lowercase().as_str() {
+            "min" => Ok(AggFunction::Min),
+            "max" => Ok(AggFunction::Max),
+            "mean" => Ok(AggFunction::Mean),
+            "sum" => Ok(AggFunction::Sum),
+            "range" => Ok(AggFunction::Range),
+            "count" => Ok(AggFunction::Count),
+            "coverage" => Ok(AggFunction::Coverage),
+            other => other
+                .strip_prefix("percentile")
+                .and_then(|p| p.parse::<f64>().ok())
+                .filter(|p| (0.0..=100.0).contains(p))
+                .map(AggFunction::Percentile)
+                .ok_or_else(|| format!("Unknown aggregation function: {s}")),
+        }
+    }
+}
+
+/// Quantile `p` (0 to 1) of `values` with linear interpolation between
+/// order statistics (R `quantile` type 7). Missing values are ignored,
+/// `NaN` if no value is left.
+#[must_use]
+pub fn quantile(values: &[f64], p: f64) -> f64 {
+    let mut sorted: Vec<f64> = values.iter().copied().filter(|v| !v.is_nan()).collect();
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    sorted.sort_by(f64::total_cmp);
+    let h = (sorted.len() - 1) as f64 * p.clamp(0.0, 1.0);
+    let lo = h.floor();
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let (i, j) = (lo as usize, h.ceil() as usize);
+    sorted[i] + (h - lo) * (sorted[j] - sorted[i])
+}
+
+/// Options of [`mc_agg`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggOptions {
+    pub period: Period,
+    pub functions: Vec<AggFunction>,
+    /// Minimal fraction (0 to 1) of expected records with a value; periods
+    /// below it get missing values (myClim `min_coverage`)
+    pub min_coverage: f64,
+    /// Logging step used to compute the expected number of records,
+    /// detected from the data if `None`
+    pub step: Option<TimeDelta>,
+}
+
+impl Default for AggOptions {
+    fn default() -> Self {
+        Self {
+            period: Period::Day,
+            functions: vec![AggFunction::Min, AggFunction::Max, AggFunction::Mean],
+            min_coverage: 1.0,
+            step: None,
+        }
+    }
+}
+
+/// Aggregated values of one period, in the column order of [`AggTable`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggRow {
+    pub start: NaiveDateTime,
+    /// Exclusive end of the period
+    pub end: NaiveDateTime,
+    pub values: Vec<f64>,
+}
+
+/// Result of [`mc_agg`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggTable {
+    /// `<variable>_<function>` for every variable and function
+    pub columns: Vec<String>,
+    pub rows: Vec<AggRow>,
+}
+
+impl AggTable {
+    /// Values of column `name`, one per period.
+    #[must_use]
+    pub fn column(&self, name: &str) -> Option<Vec<f64>> {
+        let idx = self.columns.iter().position(|c| c == name)?;
+        Some(self.rows.iter().map(|r| r.values[idx]).collect())
+    }
+}
+
+fn apply(function: AggFunction, values: &[f64], coverage: f64) -> f64 {
+    let valid = || values.iter().copied().filter(|v| !v.is_nan());
+    let count = valid().count();
+    if count == 0 && !matches!(function, AggFunction::Count | AggFunction::Coverage) {
+        return f64::NAN;
+    }
+    match function {
+        AggFunction::Min => valid().fold(f64::INFINITY, f64::min),
+        AggFunction::Max => valid().fold(f64::NEG_INFINITY, f64::max),
+        AggFunction::Mean => valid().sum::<f64>() / count as f64,
+        AggFunction::Sum => valid().sum(),
+        AggFunction::Range => {
+            valid().fold(f64::NEG_INFINITY, f64::max) - valid().fold(f64::INFINITY, f64::min)
+        }
+        AggFunction::Count => count as f64,
+        AggFunction::Coverage => coverage,
+        AggFunction::Percentile(p) => quantile(values, p / 100.0),
+    }
+}
+
+/// Start, (exclusive) end and records of a period.
+pub(crate) type PeriodGroup<'a, T> = (NaiveDateTime, NaiveDateTime, Vec<&'a T>);
+
+/// Group consecutive records falling into the same `period`. The whole
+/// series ends one `step` after its last record.
+pub(crate) fn group_by_period<T: TimeSeriesRecord>(
+    records: &[T],
+    period: Period,
+    step: Option<TimeDelta>,
+) -> Vec<PeriodGroup<'_, T>> {
+    let mut groups: Vec<PeriodGroup<'_, T>> = Vec::new();
+    for rec in records {
+        let bounds = match period {
+            Period::All => match (records.first(), records.last()) {
+                (Some(first), Some(last)) => {
+                    Some((first.datetime(), last.datetime() + step.unwrap_or_default()))
+                }
+                _ => None,
+            },
+            period => period.bounds(rec.datetime()),
+        };
+        let Some((start, end)) = bounds else {
+            continue;
+        };
+        match groups.last_mut() {
+            Some((s, _, members)) if *s == start => members.push(rec),
+            _ => groups.push((start, end, vec![rec])),
+        }
+    }
+    groups
+}
+
+/// A named value extracted from the records to aggregate.
+pub type AggVariable<'a, T> = (&'a str, &'a dyn Fn(&T) -> f64);
+
+/// Aggregate `variables` of a time series per period (myClim `mc_agg`).
+///
+/// Records are expected in time order (see [`crate::clean`]). For every
+/// period, a variable whose coverage (non-missing values over the number of
+/// records expected from the logging step) is below `min_coverage` gets
+/// missing values for all functions except `count` and `coverage`.
+///
+/// # Errors
+///
+/// Returns an error if `min_coverage` is outside 0 to 1 or no function is
+/// given.
+pub fn mc_agg<T: TimeSeriesRecord>(
+    records: &[T],
+    variables: &[AggVariable<'_, T>],
+    options: &AggOptions,
+) -> Result<AggTable> {
+    if !(0.0..=1.0).contains(&options.min_coverage) {
+        bail!("min_coverage must be between 0 and 1");
+    }
+    if options.functions.is_empty() {
+        bail!("At least one aggregation function is required");
+    }
+
+    let columns = variables
+        .iter()
+        .flat_map(|(name, _)| {
+            options
+                .functions
+                .iter()
+                .map(move |f| format!("{name}_{}", f.name()))
+        })
+        .collect();
+
+    let datetimes: Vec<NaiveDateTime> = records.iter().map(TimeSeriesRecord::datetime).collect();
+    let step = options.step.or_else(|| detect_step(&datetimes));
+
+    let rows = group_by_period(records, options.period, step)
+        .into_iter()
+        .map(|(start, end, members)| {
+            let expected = step.map_or(members.len() as f64, |step| {
+                ((end - start).num_seconds() as f64 / step.num_seconds() as f64).max(1.0)
+            });
+            let mut values = Vec::new();
+            for (_, get) in variables {
+                let series: Vec<f64> = members.iter().map(|r| get(r)).collect();
+                let coverage =
+                    (series.iter().filter(|v| !v.is_nan()).count() as f64 / expected).min(1.0);
+                for f in &options.functions {
+                    let covered = coverage >= options.min_coverage
+                        || matches!(f, AggFunction::Count | AggFunction::Coverage);
+                    values.push(if covered {
+                        apply(*f, &series, coverage)
+                    } else {
+                        f64::NAN
+                    });
+                }
+            }
+            AggRow { start, end, values }
+        })
+        .collect();
+
+    Ok(AggTable { columns, rows })
+}
@@ -0,0 +1,365 @@
+/*
+ * Batch Processing
+ *
+ * Processing of a folder of TOMST TMS logger files, with the soil type,
+ * locality and deployment of each logger taken from a locality metadata
+ * table (similar to the myClim `mc_read_data` files/localities tables).
+ * Original myClim package: https://github.com/ibot-geoecology/myClim
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+use crate::calibration::{parse_table_datetime, CalibrationTable};
+use crate::deployment::{detect_deployment, Deployment, DetectOptions};
+use crate::soil::SoilRegistry;
+use crate::tomst::TmsReader;
+use crate::vwc::{
+    logger_serial_from_path, process_records, TempSensor, VwcOptions, VwcParams, VwcReader,
+    VwcRecord,
+};
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDateTime;
+use csv::ReaderBuilder;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Locality and deployment of one logger.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LocalityMetadata {
+    /// Logger serial number, as in `data_<serial>_*.csv`
+    pub serial: String,
+    pub locality: String,
+    /// Soil type name, resolved with a [`SoilRegistry`]
+    pub soil: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub elevation: Option<f64>,
+    /// Installation datetime of the logger
+    pub installed: Option<NaiveDateTime>,
+    /// Removal datetime of the logger
+    pub removed: Option<NaiveDateTime>,
+}
+
+impl LocalityMetadata {
+    #[must_use]
+    pub fn deployment(&self) -> Deployment {
+        Deployment {
+            installed: self.installed,
+            removed: self.removed,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct MetadataRow {
+    #[serde(alias = "serial_number", alias = "logger")]
+    serial: String,
+    #[serde(alias = "locality_id")]
+    locality: String,
+    #[serde(default, alias = "soil_type")]
+    soil: Option<String>,
+    #[serde(default, alias = "lat")]
+    latitude: Option<f64>,
+    #[serde(default, alias = "lon")]
+    longitude: Option<f64>,
+    #[serde(default, alias = "altitude")]
+    elevation: Option<f64>,
+    #[serde(default, alias = "install_date", alias = "start")]
+    installed: Option<String>,
+    #[serde(default, alias = "removal_date", alias = "end")]
+    removed: Option<String>,
+}
+
+/// Locality metadata of any number of loggers.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetadataTable {
+    pub entries: Vec<LocalityMetadata>,
+}
+
+fn parse_optional_datetime(s: Option<String>) -> Result<Option<NaiveDateTime>> {
+    s.filter(|s| !s.trim().is_empty())
+        .map(|s| parse_table_datetime(&s))
+        .transpose()
+}
+
+impl MetadataTable {
+    /// Read a locality metadata table from a comma separated file.
+    ///
+    /// Expected columns: `serial` (or `serial_number`), `locality` (or
+    /// `locality_id`) and optionally `soil`, `latitude`, `longitude`,
+    /// `elevation`, `installed` and `removed` (same datetime formats as
+    /// [`CalibrationTable::from_path`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or a row cannot be parsed.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_reader(std::fs::File::open(path)?)
+    }
+
+    /// Read a locality metadata table from any reader, see
+    /// [`MetadataTable::from_path`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a row cannot be parsed.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        let mut rdr = ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut entries = Vec::new();
+        for result in rdr.deserialize() {
+            let row: MetadataRow = result?;
+            entries.push(LocalityMetadata {
+                installed: parse_optional_datetime(row.installed)
+                    .with_context(|| format!("Logger {}", row.serial))?,
+                removed: parse_optional_datetime(row.removed)
+                    .with_context(|| format!("Logger {}", row.serial))?,
+                serial: row.serial,
+                locality: row.locality,
+                soil: row.soil.filter(|s| !s.is_empty()),
+                latitude: row.latitude,
+                longitude: row.longitude,
+                elevation: row.elevation,
+            });
+        }
+        Ok(Self { entries })
+    }
+
+    /// Metadata of the logger `serial`.
+    #[must_use]
+    pub fn get(&self, serial: &str) -> Option<&LocalityMetadata> {
+        self.entries.iter().find(|e| e.serial == serial)
+    }
+
+    /// Locality id of the logger `serial`, the serial itself when it is
+    /// missing from the table (as [`LoggerSeries::locality`]).
+    #[must_use]
+    pub fn locality<'a>(&'a self, serial: &'a str) -> &'a str {
+        self.get(serial).map_or(serial, |m| &m.locality)
+    }
+}
+
+/// TOMST data files (`data_*.csv`) of `dir`, sorted by name.
+///
+/// # Errors
+///
+/// Returns an error if the directory cannot be read.
+pub fn find_tms_files<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_data = path.file_name().and_then(|n| n.to_str()).is_some_and(|n| {
+            n.starts_with("data_")
+                && Path::new(n)
+                    .extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case("csv"))
+        });
+        if is_data && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Options of [`process_batch`].
+#[derive(Debug, Clone, Default)]
+pub struct BatchOptions {
+    /// Frozen soil policy, read settings and default deployment period; the
+    /// calibration is looked up per logger in `calibration`
+    pub vwc: VwcOptions,
+    /// Calibration table with rows per serial or locality
+    pub calibration: Option<CalibrationTable>,
+    /// Soil types available to the metadata table
+    pub registry: SoilRegistry,
+    /// Soil type of loggers without one in the metadata
+    pub default_soil: Option<String>,
+    pub temp_sensor: TempSensor,
+    /// Detect the installation and removal of loggers missing them in the
+    /// metadata
+    pub detect_deployment: Option<DetectOptions>,
+    /// Number of worker threads, all available cores if 0
+    pub threads: usize,
+}
+
+/// VWC series of one logger file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoggerSeries {
+    pub path: PathBuf,
+    pub serial: String,
+    pub metadata: Option<LocalityMetadata>,
+    pub records: Vec<VwcRecord>,
+}
+
+impl LoggerSeries {
+    /// Locality id, the serial for loggers missing from the metadata.
+    #[must_use]
+    pub fn locality(&self) -> &str {
+        self.metadata.as_ref().map_or(&self.serial, |m| &m.locality)
+    }
+}
+
+/// File name part for `locality`: lowercase, with every character outside
+/// `[a-z0-9_-]` replaced by `_`, so that a locality cannot point outside the
+/// output directory and localities differing only in case share a file.
+#[must_use]
+pub fn locality_file_stem(locality: &str) -> String {
+    locality
+        .chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9' | '_' | '-') => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Process a single logger file with the metadata of its serial. Records
+/// outside the deployment period of the metadata are dropped.
+///
+/// # Errors
+///
+/// Returns an error if the soil type is unknown or missing, or the file
+/// cannot be read.
+pub fn process_logger<P: AsRef<Path>>(
+    path: P,
+    metadata: &MetadataTable,
+    options: &BatchOptions,
+) -> Result<LoggerSeries> {
+    let path = path.as_ref();
+    let serial = logger_serial_from_path(path)
+        .ok_or_else(|| anyhow!("Cannot derive logger serial from {}", path.display()))?;
+    let meta = metadata.get(&serial).cloned();
+
+    let soil_name = meta
+        .as_ref()
+        .and_then(|m| m.soil.as_deref())
+        .or(options.default_soil.as_deref())
+        .ok_or_else(|| anyhow!("No soil type for logger {serial}"))?;
+    let params = VwcParams {
+        soil: options.registry.resolve(soil_name)?,
+        temp_sensor: options.temp_sensor,
+    };
+
+    let mut vwc = options.vwc.clone();
+    if let Some(table) = &options.calibration {
+        // Serial rows first, then locality rows
+        vwc.calibration = table.schedule(&serial);
+        if let (true, Some(m)) = (vwc.calibration.is_empty(), &meta) {
+            vwc.calibration = table.schedule(&m.locality);
+        }
+    }
+
+    vwc.deployment = meta
+        .as_ref()
+        .map_or(vwc.deployment, |m| m.deployment().or(vwc.deployment));
+
+    let records = match options.detect_deployment {
+        Some(detect) if vwc.deployment.installed.is_none() || vwc.deployment.removed.is_none() => {
+            let tms = TmsReader::with_options(std::fs::File::open(path)?, vwc.read)
+                .collect::<Result<Vec<_>>>()
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            vwc.deployment = vwc.deployment.or(detect_deployment(&tms, &detect));
+            process_records(&tms, params, &vwc)
+        }
+        _ => VwcReader::from_path(path, params, vwc)?
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("Failed to process {}", path.display()))?,
+    };
+    Ok(LoggerSeries {
+        path: path.to_path_buf(),
+        serial,
+        metadata: meta,
+        records,
+    })
+}
+
+/// Number of worker threads for `files` files, all available cores if
+/// `threads` is 0.
+fn worker_count(threads: usize, files: usize) -> usize {
+    match threads {
+        0 => std::thread::available_parallelism().map_or(1, std::num::NonZero::get),
+        n => n,
+    }
+    .clamp(1, files.max(1))
+}
+
+/// Process logger files in parallel with [`process_logger`]. Results are
+/// returned in the order of `files`; a failing file does not stop the
+/// others.
+///
+/// # Panics
+///
+/// Propagates a panic of a worker thread.
+#[must_use]
+pub fn process_batch(
+    files: &[PathBuf],
+    metadata: &MetadataTable,
+    options: &BatchOptions,
+) -> Vec<Result<LoggerSeries>> {
+    let threads = worker_count(options.threads, files.len());
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, Result<LoggerSeries>)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(path) = files.get(i) else {
+                            break done;
+                        };
+                        done.push((i, process_logger(path, metadata, options)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("batch worker panicked"))
+            .collect()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Same as [`process_batch`], handing the result of every file to `sink` in
+/// the order of `files` instead of returning them all. Files are processed
+/// in chunks of two per worker thread, so that memory use does not grow
+/// with the number of loggers.
+///
+/// # Errors
+///
+/// Returns the first error of `sink`, the remaining files are not
+/// processed.
+///
+/// # Panics
+///
+/// Propagates a panic of a worker thread.
+pub fn process_batch_with<F>(
+    files: &[PathBuf],
+    metadata: &MetadataTable,
+    options: &BatchOptions,
+    mut sink: F,
+) -> Result<()>
+where
+    F: FnMut(&Path, Result<LoggerSeries>) -> Result<()>,
+{
+    let chunk = 2 * worker_count(options.threads, files.len());
+    for files in files.chunks(chunk) {
+        for (path, result) in files.iter().zip(process_batch(files, metadata, options)) {
+            sink(path, result)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,178 @@
+/*
+ * TMS Moisture Calibration
+ *
+ * Field calibration of TMS moisture counts, following the myClim R package
+ * functions `mc_prep_calib_load` and `mc_prep_calib`.
+ * Original myClim package: https://github.com/ibot-geoecology/myClim
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+use anyhow::{anyhow, Result};
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use csv::ReaderBuilder;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::Path;
+
+/// Sensor id used by myClim for the TMS moisture count.
+pub const TMS_MOIST_SENSOR_ID: &str = "TMS_moist";
+
+/// Correction applied to the temperature-corrected raw count before the
+/// second VWC evaluation: `raw' = tcor + cor_factor + cor_slope · vwc`.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Calibration {
+    pub cor_factor: f64,
+    pub cor_slope: f64,
+}
+
+/// One row of a calibration table, valid from `datetime` until the next
+/// entry with the same `id`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationEntry {
+    /// Logger serial number or locality id the correction belongs to
+    pub id: String,
+    pub datetime: NaiveDateTime,
+    pub calibration: Calibration,
+}
+
+/// Calibration rows as found in a myClim `mc_prep_calib_load` table.
+#[derive(Debug, Deserialize)]
+struct CalibrationRow {
+    #[serde(alias = "serial_number", alias = "locality_id")]
+    id: String,
+    #[serde(default)]
+    sensor_id: Option<String>,
+    datetime: String,
+    cor_factor: f64,
+    cor_slope: f64,
+}
+
+/// Calibration table covering any number of loggers or localities.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationTable {
+    pub entries: Vec<CalibrationEntry>,
+}
+
+/// Time-ordered calibrations of a single logger or locality.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationSchedule {
+    points: Vec<(NaiveDateTime, Calibration)>,
+}
+
+/// Datetime of a metadata or calibration table row: `%Y-%m-%d %H:%M:%S`,
+/// `%Y-%m-%d %H:%M`, `%Y.%m.%d %H:%M`, or `%Y-%m-%d` meaning midnight.
+///
+/// # Errors
+///
+/// Returns an error if `s` matches none of the formats.
+pub fn parse_table_datetime(s: &str) -> Result<NaiveDateTime> {
+    let s = s.trim();
+    for fmt in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y.%m.%d %H:%M"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(s, fmt) {
+            return Ok(dt);
+        }
+    }
+    let date =
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(|_| anyhow!("Invalid datetime: {s}"))?;
+    Ok(date.and_time(NaiveTime::MIN))
+}
+
+impl CalibrationTable {
+    /// Read a calibration table from a comma separated file.
+    ///
+    /// Expected columns (myClim `mc_prep_calib_load` layout):
+    /// `serial_number` (or `locality_id`), `sensor_id` (optional),
+    /// `datetime`, `cor_factor`, `cor_slope`. Rows whose `sensor_id` is set
+    /// to anything other than `TMS_moist` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, a row cannot be parsed,
+    /// or a datetime is not in `%Y-%m-%d %H:%M:%S`, `%Y-%m-%d %H:%M`,
+    /// `%Y-%m-%d` or `%Y.%m.%d %H:%M` format.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_reader(std::fs::File::open(path)?)
+    }
+
+    /// Read a calibration table from any reader, see [`CalibrationTable::from_path`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a row cannot be parsed.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        let mut rdr = ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut entries = Vec::new();
+        for result in rdr.deserialize() {
+            let row: CalibrationRow = result?;
+            if row
+                .sensor_id
+                .as_deref()
+                .is_some_and(|s| !s.is_empty() && s != TMS_MOIST_SENSOR_ID)
+            {
+                continue;
+            }
+            entries.push(CalibrationEntry {
+                id: row.id,
+                datetime: parse_table_datetime(&row.datetime)?,
+                calibration: Calibration {
+                    cor_factor: row.cor_factor,
+                    cor_slope: row.cor_slope,
+                },
+            });
+        }
+        Ok(Self { entries })
+    }
+
+    /// Calibrations of the logger or locality `id`, ordered in time.
+    #[must_use]
+    pub fn schedule(&self, id: &str) -> CalibrationSchedule {
+        let mut points: Vec<(NaiveDateTime, Calibration)> = self
+            .entries
+            .iter()
+            .filter(|e| e.id == id)
+            .map(|e| (e.datetime, e.calibration))
+            .collect();
+        points.sort_by_key(|(dt, _)| *dt);
+        CalibrationSchedule { points }
+    }
+}
+
+impl CalibrationSchedule {
+    /// Schedule applying a single calibration to the whole series.
+    #[must_use]
+    pub fn constant(calibration: Calibration) -> Self {
+        Self {
+            points: vec![(NaiveDateTime::MIN, calibration)],
+        }
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Calibration in force at `datetime`.
+    ///
+    /// As in myClim, each calibration applies from its datetime until the
+    /// next one; records before the first calibration are uncorrected.
+    #[must_use]
+    pub fn at(&self, datetime: NaiveDateTime) -> Calibration {
+        let idx = self.points.partition_point(|(dt, _)| *dt <= datetime);
+        if idx == 0 {
+            Calibration::default()
+        } else {
+            self.points[idx - 1].1
+        }
+    }
+}
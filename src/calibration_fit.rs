@@ -0,0 +1,237 @@
+/*
+ * TMS Moisture Calibration Fitting
+ *
+ * Derives `cor_factor` / `cor_slope` calibration parameters from gravimetric
+ * reference samples, following the myClim R package function
+ * `mc_calib_moisture`.
+ * Original myClim package: https://github.com/ibot-geoecology/myClim
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+#![allow(clippy::cast_precision_loss, clippy::similar_names)]
+
+use crate::calibration::{parse_table_datetime, Calibration, CalibrationEntry};
+use crate::vwc::{
+    curve_count, mc_calc_vwc_calibrated, temperature_corrected, VwcParams, VwcRecord,
+};
+use anyhow::{bail, Result};
+use chrono::{NaiveDateTime, TimeDelta};
+use csv::ReaderBuilder;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::Path;
+
+/// Laboratory VWC measured on a soil core taken next to a logger.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReferenceSample {
+    pub datetime: NaiveDateTime,
+    /// Logger serial number the core belongs to
+    pub logger: String,
+    /// Reference volumetric water content (0.0 to 1.0)
+    pub vwc: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReferenceRow {
+    #[serde(alias = "serial_number")]
+    logger: String,
+    datetime: String,
+    #[serde(alias = "ref_moist", alias = "ref_vwc")]
+    vwc: f64,
+}
+
+/// A reference sample paired with the logger reading closest in time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchedSample {
+    pub sample: ReferenceSample,
+    /// Datetime of the matched logger record
+    pub record_datetime: NaiveDateTime,
+    pub raw: f64,
+    pub temp: f64,
+}
+
+/// Fitted calibration and how well it reproduces the reference samples.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationFit {
+    pub calibration: Calibration,
+    /// Reference VWC minus calibrated VWC, one per sample
+    pub residuals: Vec<f64>,
+    /// Root mean square of `residuals`
+    pub rmse: f64,
+}
+
+impl CalibrationFit {
+    /// Calibration table row applying this fit to `id` from `datetime` on.
+    #[must_use]
+    pub fn to_entry(&self, id: &str, datetime: NaiveDateTime) -> CalibrationEntry {
+        CalibrationEntry {
+            id: id.to_string(),
+            datetime,
+            calibration: self.calibration,
+        }
+    }
+}
+
+/// Read reference samples from a comma separated file with columns
+/// `logger` (or `serial_number`), `datetime` (same layouts as calibration
+/// tables, see [`parse_table_datetime`]) and `vwc` (or `ref_moist`).
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or a row cannot be parsed.
+pub fn read_reference_samples<P: AsRef<Path>>(path: P) -> Result<Vec<ReferenceSample>> {
+    read_reference_samples_from(std::fs::File::open(path)?)
+}
+
+/// Same as [`read_reference_samples`] for any reader.
+///
+/// # Errors
+///
+/// Returns an error if a row cannot be parsed.
+pub fn read_reference_samples_from<R: Read>(reader: R) -> Result<Vec<ReferenceSample>> {
+    let mut rdr = ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let mut out = Vec::new();
+    for result in rdr.deserialize() {
+        let row: ReferenceRow = result?;
+        out.push(ReferenceSample {
+            datetime: parse_table_datetime(&row.datetime)?,
+            logger: row.logger,
+            vwc: row.vwc,
+        });
+    }
+    Ok(out)
+}
+
+/// Pair each sample of `logger` with the closest record of `series`
+/// (as returned by [`crate::process_file`]). Samples without a record within
+/// `max_offset` are dropped.
+#[must_use]
+pub fn match_samples(
+    samples: &[ReferenceSample],
+    logger: &str,
+    series: &[VwcRecord],
+    max_offset: TimeDelta,
+) -> Vec<MatchedSample> {
+    samples
+        .iter()
+        .filter(|s| s.logger == logger)
+        .filter_map(|s| {
+            let rec = series
+                .iter()
+                .min_by_key(|r| (r.datetime - s.datetime).abs())?;
+            ((rec.datetime - s.datetime).abs() <= max_offset).then(|| MatchedSample {
+                sample: s.clone(),
+                record_datetime: rec.datetime,
+                raw: rec.raw,
+                temp: rec.temp,
+            })
+        })
+        .collect()
+}
+
+/// Fit `cor_factor` and `cor_slope` from raw counts, temperatures and
+/// reference VWC values (myClim `mc_calib_moisture`).
+///
+/// For every sample the count shift needed for the temperature-corrected raw
+/// value to reproduce the reference VWC is computed, then regressed against
+/// the uncorrected VWC estimate: `shift = cor_factor + cor_slope · vwc`.
+/// With a single sample (or no spread in VWC) only `cor_factor` is fitted.
+/// The soil curve and its temperature correction constants are those of
+/// `soil`, as applied by [`mc_calc_vwc_calibrated`]; `temp` must be the
+/// temperature of its `temp_sensor`.
+///
+/// # Errors
+///
+/// Returns an error if the slices differ in length, are empty, or a
+/// reference VWC is outside the range of the soil curve.
+pub fn mc_calib_moisture(
+    raw: &[f64],
+    temp: &[f64],
+    ref_vwc: &[f64],
+    soil: impl Into<VwcParams>,
+) -> Result<CalibrationFit> {
+    if raw.len() != temp.len() || raw.len() != ref_vwc.len() {
+        bail!("raw, temp and ref_vwc must have the same length");
+    }
+    if raw.is_empty() {
+        bail!("At least one reference sample is required");
+    }
+
+    let params = soil.into();
+    let soil = params.soil;
+    let mut vwc = Vec::with_capacity(raw.len());
+    let mut shift = Vec::with_capacity(raw.len());
+    for i in 0..raw.len() {
+        let target = curve_count(ref_vwc[i], &soil);
+        if target.is_nan() {
+            bail!(
+                "Reference VWC {} is out of range of the soil curve",
+                ref_vwc[i]
+            );
+        }
+        vwc.push(soil.curve(raw[i]));
+        shift.push(target - temperature_corrected(raw[i], temp[i], &soil));
+    }
+
+    let n = raw.len() as f64;
+    let vwc_mean = vwc.iter().sum::<f64>() / n;
+    let shift_mean = shift.iter().sum::<f64>() / n;
+    let ss_xx: f64 = vwc.iter().map(|v| (v - vwc_mean).powi(2)).sum();
+    let ss_xy: f64 = vwc
+        .iter()
+        .zip(&shift)
+        .map(|(v, s)| (v - vwc_mean) * (s - shift_mean))
+        .sum();
+    let cor_slope = if ss_xx < 1e-12 { 0.0 } else { ss_xy / ss_xx };
+    let calibration = Calibration {
+        cor_factor: shift_mean - cor_slope * vwc_mean,
+        cor_slope,
+    };
+
+    let residuals: Vec<f64> = (0..raw.len())
+        .map(|i| ref_vwc[i] - mc_calc_vwc_calibrated(raw[i], temp[i], params, calibration))
+        .collect();
+    let rmse = (residuals.iter().map(|r| r * r).sum::<f64>() / n).sqrt();
+
+    Ok(CalibrationFit {
+        calibration,
+        residuals,
+        rmse,
+    })
+}
+
+/// Match the samples of `logger` against its TMS series and fit a
+/// calibration with [`mc_calib_moisture`]. `series` must have been computed
+/// with the temperature sensor of `soil`.
+///
+/// # Errors
+///
+/// Returns an error if no sample can be matched or the fit fails.
+pub fn fit_logger_calibration(
+    samples: &[ReferenceSample],
+    logger: &str,
+    series: &[VwcRecord],
+    soil: impl Into<VwcParams>,
+    max_offset: TimeDelta,
+) -> Result<(Vec<MatchedSample>, CalibrationFit)> {
+    let matched = match_samples(samples, logger, series, max_offset);
+    if matched.is_empty() {
+        bail!("No reference sample of {logger} matches a logger record");
+    }
+    let raw: Vec<f64> = matched.iter().map(|m| m.raw).collect();
+    let temp: Vec<f64> = matched.iter().map(|m| m.temp).collect();
+    let ref_vwc: Vec<f64> = matched.iter().map(|m| m.sample.vwc).collect();
+    let fit = mc_calib_moisture(&raw, &temp, &ref_vwc, soil)?;
+    Ok((matched, fit))
+}
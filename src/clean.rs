@@ -0,0 +1,269 @@
+/*
+ * Time Series Cleaning
+ *
+ * Cleaning of logger time series, following the myClim R package functions
+ * `mc_prep_clean` and `mc_info_clean`.
+ * Original myClim package: https://github.com/ibot-geoecology/myClim
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+use crate::tomst::{DendroRecord, ThermoRecord, TmsRecord};
+use crate::vwc::VwcRecord;
+use anyhow::{bail, Result};
+use chrono::{NaiveDateTime, TimeDelta};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A record of a regular logger time series.
+pub trait TimeSeriesRecord: Clone {
+    fn datetime(&self) -> NaiveDateTime;
+
+    fn set_datetime(&mut self, datetime: NaiveDateTime);
+
+    /// Record at `datetime` with all measurements missing, used to fill gaps.
+    /// Metadata such as the time zone is taken from `self`; the logger
+    /// record index is not, as the record was not logged.
+    #[must_use]
+    fn missing_at(&self, datetime: NaiveDateTime) -> Self;
+}
+
+impl TimeSeriesRecord for TmsRecord {
+    fn datetime(&self) -> NaiveDateTime {
+        self.datetime
+    }
+
+    fn set_datetime(&mut self, datetime: NaiveDateTime) {
+        self.datetime = datetime;
+    }
+
+    fn missing_at(&self, datetime: NaiveDateTime) -> Self {
+        Self {
+            datetime,
+            t1: f64::NAN,
+            t2: f64::NAN,
+            t3: f64::NAN,
+            moisture: f64::NAN,
+            index: None,
+            shake: 0,
+            err_flag: 0,
+            ..*self
+        }
+    }
+}
+
+impl TimeSeriesRecord for ThermoRecord {
+    fn datetime(&self) -> NaiveDateTime {
+        self.datetime
+    }
+
+    fn set_datetime(&mut self, datetime: NaiveDateTime) {
+        self.datetime = datetime;
+    }
+
+    fn missing_at(&self, datetime: NaiveDateTime) -> Self {
+        Self {
+            datetime,
+            index: None,
+            t: f64::NAN,
+            shake: 0,
+            err_flag: 0,
+            ..*self
+        }
+    }
+}
+
+impl TimeSeriesRecord for DendroRecord {
+    fn datetime(&self) -> NaiveDateTime {
+        self.datetime
+    }
+
+    fn set_datetime(&mut self, datetime: NaiveDateTime) {
+        self.datetime = datetime;
+    }
+
+    fn missing_at(&self, datetime: NaiveDateTime) -> Self {
+        Self {
+            datetime,
+            t: f64::NAN,
+            raw: f64::NAN,
+            um: f64::NAN,
+            index: None,
+            shake: 0,
+            err_flag: 0,
+            ..*self
+        }
+    }
+}
+
+impl TimeSeriesRecord for VwcRecord {
+    fn datetime(&self) -> NaiveDateTime {
+        self.datetime
+    }
+
+    fn set_datetime(&mut self, datetime: NaiveDateTime) {
+        self.datetime = datetime;
+    }
+
+    fn missing_at(&self, datetime: NaiveDateTime) -> Self {
+        Self {
+            datetime,
+            raw: f64::NAN,
+            temp: f64::NAN,
+            vwc: f64::NAN,
+            frozen: false,
+            ..*self
+        }
+    }
+}
+
+/// Options of [`clean`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CleanOptions {
+    /// Logging step, detected from the data if `None`
+    pub step: Option<TimeDelta>,
+    /// Round datetimes that are not aligned to the step
+    pub round: bool,
+    /// Insert records with missing values where the series has gaps
+    pub fill_gaps: bool,
+}
+
+impl Default for CleanOptions {
+    fn default() -> Self {
+        Self {
+            step: None,
+            round: true,
+            fill_gaps: true,
+        }
+    }
+}
+
+/// Summary of a cleaning run (myClim `mc_info_clean`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CleanReport {
+    pub start_date: NaiveDateTime,
+    pub end_date: NaiveDateTime,
+    /// Logging step in minutes
+    pub step_minutes: i64,
+    /// Records removed because of a repeated datetime
+    pub count_duplicities: usize,
+    /// Records inserted (or missing, if gaps are not filled) in gaps
+    pub count_missing: usize,
+    /// Records with a datetime earlier than the previous record
+    pub count_disordered: usize,
+    /// Records whose datetime was rounded to the step
+    pub count_rounded: usize,
+    /// Gaps longer than one step, as (last record before, first record after)
+    pub gaps: Vec<(NaiveDateTime, NaiveDateTime)>,
+}
+
+/// Most frequent positive difference between consecutive datetimes,
+/// the shortest one on ties.
+#[must_use]
+pub fn detect_step(datetimes: &[NaiveDateTime]) -> Option<TimeDelta> {
+    let mut sorted = datetimes.to_vec();
+    sorted.sort_unstable();
+    let mut counts: HashMap<TimeDelta, usize> = HashMap::new();
+    for w in sorted.windows(2) {
+        let diff = w[1] - w[0];
+        if diff > TimeDelta::zero() {
+            *counts.entry(diff).or_insert(0) += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by(|(d1, c1), (d2, c2)| c1.cmp(c2).then(d2.cmp(d1)))
+        .map(|(d, _)| d)
+}
+
+/// Round `datetime` to the nearest multiple of `step` since midnight.
+fn round_to_step(datetime: NaiveDateTime, step: TimeDelta) -> NaiveDateTime {
+    let step_secs = step.num_seconds();
+    let midnight = datetime.date().and_time(chrono::NaiveTime::MIN);
+    let secs = (datetime - midnight).num_seconds();
+    let rounded = (secs + step_secs / 2).div_euclid(step_secs) * step_secs;
+    midnight + TimeDelta::seconds(rounded)
+}
+
+/// Clean a logger time series like myClim `mc_prep_clean`: detect the
+/// logging step, round misaligned datetimes, sort, remove duplicated
+/// datetimes (keeping the first record) and fill gaps with missing records.
+///
+/// # Errors
+///
+/// Returns an error if the step cannot be detected (fewer than two distinct
+/// datetimes) or is not a positive whole number of seconds.
+pub fn clean<T: TimeSeriesRecord>(
+    records: &[T],
+    options: &CleanOptions,
+) -> Result<(Vec<T>, CleanReport)> {
+    let datetimes: Vec<NaiveDateTime> = records.iter().map(TimeSeriesRecord::datetime).collect();
+    let Some(step) = options.step.or_else(|| detect_step(&datetimes)) else {
+        bail!("Cannot detect the logging step of a series with fewer than two datetimes");
+    };
+    if step.num_seconds() <= 0 || step.subsec_nanos() != 0 {
+        bail!("Invalid logging step: {step}");
+    }
+
+    let count_disordered = datetimes.windows(2).filter(|w| w[1] < w[0]).count();
+
+    let mut out: Vec<T> = records.to_vec();
+    let mut count_rounded = 0;
+    if options.round {
+        for rec in &mut out {
+            let rounded = round_to_step(rec.datetime(), step);
+            if rounded != rec.datetime() {
+                rec.set_datetime(rounded);
+                count_rounded += 1;
+            }
+        }
+    }
+
+    // Stable sort keeps the first of duplicated records in file order
+    out.sort_by_key(TimeSeriesRecord::datetime);
+    let before_dedup = out.len();
+    out.dedup_by_key(|r| r.datetime());
+    let count_duplicities = before_dedup - out.len();
+
+    let mut gaps = Vec::new();
+    let mut count_missing = 0;
+    let mut filled: Vec<T> = Vec::with_capacity(out.len());
+    for rec in out {
+        if let Some(prev) = filled.last() {
+            let prev_dt = prev.datetime();
+            // Whole steps missing between the two records
+            let missing = (rec.datetime() - prev_dt).num_seconds() / step.num_seconds() - 1;
+            if missing > 0 {
+                let template = prev.clone();
+                gaps.push((prev_dt, rec.datetime()));
+                for k in 1..=missing {
+                    count_missing += 1;
+                    if options.fill_gaps {
+                        filled.push(template.missing_at(prev_dt + step * i32::try_from(k)?));
+                    }
+                }
+            }
+        }
+        filled.push(rec);
+    }
+
+    let report = CleanReport {
+        start_date: filled.first().map(T::datetime).unwrap_or_default(),
+        end_date: filled.last().map(T::datetime).unwrap_or_default(),
+        step_minutes: step.num_minutes(),
+        count_duplicities,
+        count_missing,
+        count_disordered,
+        count_rounded,
+        gaps,
+    };
+    Ok((filled, report))
+}
@@ -0,0 +1,12 @@
+/*
+ * Command line interface
+ *
+ * Argument parsing of the single file and batch modes, and the writers of
+ * the output files and reports of each feature.
+ */
+
+mod args;
+mod output;
+
+pub use args::*;
+pub use output::*;
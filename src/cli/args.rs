@@ -0,0 +1,568 @@
+/*
+ * Command line arguments
+ *
+ * Options shared by the single file and batch modes, and the features
+ * each flag turns on.
+ */
+
+use chrono::{NaiveDateTime, TimeDelta};
+use soil_sensor_toolbox::{
+    logger_serial_from_path, parse_table_datetime, parse_utc_offset, AggFunction, AggOptions,
+    BatchOptions, CalibrationTable, DecimalSeparator, DetectOptions, DroughtOptions, FrozenPolicy,
+    HydraulicModel, IndexOptions, Interpolation, MetadataTable, MoistureSensor, Period,
+    ResampleOptions, SensorFamily, SoilEnsemble, SoilRegistry, SoilType, SoilTypeModel, Substrate,
+    TempSensor, TmsReadOptions, TomstDateFormat, VwcOptions, VwcParams, TMS4_SOILS,
+};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::process;
+
+pub fn print_usage() {
+    println!("Usage: soil-sensor-toolbox <input_file> <soil_type> [options]");
+    println!("       soil-sensor-toolbox batch <directory> <metadata.csv> [options]");
+    println!("       soil-sensor-toolbox merge <file|directory>... [--out <directory>]");
+    println!("\n<input_file> can be `-` to read from standard input. Rows are processed one");
+    println!("at a time, except with --clean, --resample, --qc, --snow, --indices, --thermal or");
+    println!("--ensemble (and --aggregate keeps the VWC series).");
+    println!("\nOptions:");
+    println!(
+        "  --clean                    Sort, remove duplicated datetimes, round to the logging"
+    );
+    println!("                             step and fill gaps (myClim mc_prep_clean)");
+    println!("  --resample <minutes>       Re-grid the series to this step, aligned to midnight,");
+    println!("                             and add a `filled` column (original, interpolated or");
+    println!("                             missing)");
+    println!("  --interpolation <method>   linear (default), nearest or none");
+    println!("  --max-gap <minutes>        Longest gap that is interpolated (default: 60)");
+    println!("  --soils <soils.csv|toml>   Add site-specific soil curves (name, a, b, c and");
+    println!("                             optionally ref_t, acor_t, wcor_t)");
+    println!("  --calib <calibration.csv>  Apply a myClim-style calibration table");
+    println!("  --serial <id>              Logger serial or locality id to look up in the");
+    println!("                             calibration table (default: from the file name)");
+    println!("  --tz <offset>              Convert datetimes to this UTC offset, e.g. +01:00");
+    println!("  --tz-offset <offset>       UTC offset of the logger clock, overriding the file's");
+    println!("                             time zone column");
+    println!("  --date-format <format>     Datetime layout of the input file (default: detected),");
+    println!("                             e.g. \"%d.%m.%Y %H:%M:%S\"");
+    println!(
+        "  --decimal <dot|comma>      Decimal separator of the input file (default: detected)"
+    );
+    println!("  --temp-sensor <t1|t2|t3>   Temperature used for the VWC correction (default: T1)");
+    println!("  --ref-t <°C>               Reference temperature of the correction (default: 24)");
+    println!("  --acor-t <value>           Correction constant acor_t (default: 1.911327)");
+    println!("  --wcor-t <value>           Correction constant wcor_t (default: 0.64108)");
+    println!("  --frozen <keep|na|flag>    VWC of frozen soil (T1 < 0 °C): keep it (default),");
+    println!("                             write NA, or keep it and add a `frozen` column");
+    println!(
+        "  --aggregate <period>       Also write output_<period>.csv aggregated per hour, day,"
+    );
+    println!("                             week, month, year, all or custom:<MM-DD>:<MM-DD>");
+    println!("  --agg-fun <f1,f2,...>      Aggregation functions: min, max, mean, sum, range,");
+    println!("                             count, coverage, percentile<p> (default: min,max,mean)");
+    println!("  --min-coverage <0-1>       Fraction of expected records required per period");
+    println!("                             (default: 1)");
+    println!("  --indices <period>         Also write indices_<period>.csv with growing and");
+    println!("                             freezing degree days, frost days and freeze-thaw");
+    println!("                             cycles per period");
+    println!("  --index-sensor <t1|t2|t3>  Temperature of the indices (default: T3)");
+    println!("  --gdd-base <°C>            Base temperature of growing degree days (default: 5)");
+    println!("  --fdd-base <°C>            Base temperature of freezing degree days (default: 0)");
+    println!("  --potential <vg|campbell>  Add matric potential (kPa) and relative extractable");
+    println!("                             water columns from a van Genuchten or Campbell curve");
+    println!("  --texture <soil_type>      Builtin soil type giving the curve parameters");
+    println!("                             (default: the soil type, required in batch mode)");
+    println!("  --drought <vwc>            Also write drought_<period>.csv with the days and time");
+    println!("                             below this VWC, the longest dry spell and VWC");
+    println!("                             percentiles per period (batch: one row per logger)");
+    println!("  --drought-period <period>  Period of the drought statistics (default: month)");
+    println!("  --installed <datetime>     Drop records before the logger installation");
+    println!("  --removed <datetime>       Drop records after the logger removal");
+    println!("  --detect-deployment        Detect installation and removal from the moisture");
+    println!("                             count and T1 variance when not given");
+    println!("  --qc                       Add a `qc` column flagging implausible counts (range),");
+    println!("                             sensor in air (air), sudden steps (step) and days");
+    println!("                             with an air-like T1 amplitude (exposure)");
+    println!("  --snow                     Add a `snow` column (1/0/NA) detected from the damped");
+    println!("                             T2 range (myClim mc_calc_snow) and print a summary");
+    println!("  --thermal                  Write thermal.csv with the daily thermal diffusivity");
+    println!("                             from the T2 and T1 diurnal cycles and add a");
+    println!("                             `heat_flux_W_m2` column (positive into the soil)");
+    println!("  --ensemble <soils|tms4>    Also write ensemble.csv with the VWC min, median, max");
+    println!("                             and spread over soil types s1,s2,... or tms4 (all)");
+    println!("  --ensemble-columns all     Add a VWC column per soil type to ensemble.csv");
+    println!("  --sensor <family>          tms (default), teros10, teros11, 5tm or smt100; the");
+    println!("                             soil type is then the substrate, mineral or organic");
+    println!("  --port <n>                 ZENTRA logger port or SMT100 probe of the sensor");
+    println!("\nBatch options (data_<serial>_*.csv files, metadata columns serial, locality,");
+    println!("soil, latitude, longitude, elevation, installed, removed):");
+    println!("  --soil <soil_type>         Soil type of loggers without one in the metadata");
+    println!("  --per-locality             Write output_<locality>.csv files instead of");
+    println!("                             output_batch.csv");
+    println!("  --out <directory>          Output directory (default: current directory)");
+    println!("  --threads <n>              Number of worker threads (default: all cores)");
+    println!("  --keep-going               Exit successfully when some files fail");
+    println!("\nMerge writes one data_<serial>_merged.csv per logger from overlapping downloads,");
+    println!("reporting duplicated records, conflicting readings and clock resets.");
+    println!("\nAvailable soil types (or a name defined with --soils):");
+    for soil in &SoilType::ALL {
+        println!("  {}", soil.as_str());
+    }
+    println!("\nExample:");
+    println!("  soil-sensor-toolbox data.csv universal");
+    println!("  soil-sensor-toolbox data.csv peat");
+    println!("  cat data_*.csv | soil-sensor-toolbox - universal");
+    println!("  soil-sensor-toolbox data_94184102_0.csv loam --calib calibration.csv");
+    println!("  soil-sensor-toolbox data.csv alpine_loam --soils soils.toml");
+    println!(
+        "  soil-sensor-toolbox data.csv universal --aggregate day --agg-fun mean,percentile95"
+    );
+    println!("  soil-sensor-toolbox batch campaign/ localities.csv --per-locality --out results/");
+    println!("  soil-sensor-toolbox merge downloads/2023_*/ --out campaign/");
+}
+
+/// Processing steps and outputs turned on by a flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Feature {
+    Clean,
+    Resample,
+    DetectDeployment,
+    Qc,
+    Snow,
+    Indices,
+    Thermal,
+    Ensemble,
+    Aggregate,
+    Drought,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 10] = [
+        Feature::Clean,
+        Feature::Resample,
+        Feature::DetectDeployment,
+        Feature::Qc,
+        Feature::Snow,
+        Feature::Indices,
+        Feature::Thermal,
+        Feature::Ensemble,
+        Feature::Aggregate,
+        Feature::Drought,
+    ];
+
+    pub fn flag(self) -> &'static str {
+        match self {
+            Feature::Clean => "--clean",
+            Feature::Resample => "--resample",
+            Feature::DetectDeployment => "--detect-deployment",
+            Feature::Qc => "--qc",
+            Feature::Snow => "--snow",
+            Feature::Indices => "--indices",
+            Feature::Thermal => "--thermal",
+            Feature::Ensemble => "--ensemble",
+            Feature::Aggregate => "--aggregate",
+            Feature::Drought => "--drought",
+        }
+    }
+
+    /// Whether the feature works on the VWC of any sensor family, not only
+    /// on the counts and temperatures of TMS loggers.
+    pub fn any_sensor(self) -> bool {
+        matches!(self, Feature::Aggregate | Feature::Drought)
+    }
+
+    /// Whether the feature needs the whole TMS series rather than streamed
+    /// rows. Deployment detection only does when a bound is not given.
+    pub fn whole_series(self) -> bool {
+        !matches!(
+            self,
+            Feature::DetectDeployment | Feature::Aggregate | Feature::Drought
+        )
+    }
+
+    /// Whether the feature is available in batch mode.
+    pub fn batch(self) -> bool {
+        matches!(self, Feature::DetectDeployment | Feature::Drought)
+    }
+
+    /// Whether the feature adds a column to output.csv, computed from the
+    /// whole series.
+    pub fn column(self) -> bool {
+        matches!(
+            self,
+            Feature::Resample | Feature::Qc | Feature::Snow | Feature::Thermal
+        )
+    }
+}
+
+/// Options shared by the single file and batch modes.
+#[derive(Default)]
+struct Options {
+    features: BTreeSet<Feature>,
+    vwc: VwcOptions,
+    resampling: ResampleOptions,
+    calib_file: Option<String>,
+    soil_files: Vec<String>,
+    temp_sensor: TempSensor,
+    ref_t: Option<f64>,
+    acor_t: Option<f64>,
+    wcor_t: Option<f64>,
+    serial: Option<String>,
+    agg: AggOptions,
+    index: IndexOptions,
+    drought: DroughtOptions,
+    ensemble: String,
+    ensemble_all: bool,
+    sensor: Option<SensorFamily>,
+    port: Option<u8>,
+    potential: Option<String>,
+    texture: Option<String>,
+    default_soil: Option<String>,
+    per_locality: bool,
+    keep_going: bool,
+    out_dir: Option<PathBuf>,
+    threads: usize,
+}
+
+pub struct CliArgs {
+    pub input_file: String,
+    pub params: VwcParams,
+    pub options: VwcOptions,
+    pub features: BTreeSet<Feature>,
+    pub resampling: ResampleOptions,
+    pub aggregate: AggOptions,
+    pub indices: IndexOptions,
+    pub drought: DroughtOptions,
+    pub ensemble: Option<SoilEnsemble>,
+    pub ensemble_all: bool,
+    /// Sensor of another family than TMS
+    pub sensor: Option<Box<dyn MoistureSensor>>,
+    pub hydraulics: Option<HydraulicModel>,
+}
+
+impl CliArgs {
+    pub fn has(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+}
+
+pub struct BatchArgs {
+    pub dir: String,
+    pub metadata: MetadataTable,
+    pub options: BatchOptions,
+    pub hydraulics: Option<HydraulicModel>,
+    pub drought: Option<DroughtOptions>,
+    pub per_locality: bool,
+    /// Files that fail do not make the batch fail
+    pub keep_going: bool,
+    pub out_dir: PathBuf,
+}
+
+fn parse_number(flag: &str, value: &str) -> Result<f64, String> {
+    value
+        .parse()
+        .map_err(|e| format!("Invalid {flag} value {value}: {e}"))
+}
+
+fn parse_minutes(flag: &str, value: &str) -> Result<TimeDelta, String> {
+    match value.parse() {
+        Ok(minutes) if minutes > 0 => Ok(TimeDelta::minutes(minutes)),
+        Ok(_) => Err(format!("Invalid {flag} value {value}: must be positive")),
+        Err(e) => Err(format!("Invalid {flag} value {value}: {e}")),
+    }
+}
+
+fn parse_datetime(flag: &str, value: &str) -> Result<NaiveDateTime, String> {
+    parse_table_datetime(value).map_err(|e| format!("Invalid {flag} value: {e}"))
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut opts = Options::default();
+    let mut rest = args.iter();
+    while let Some(flag) = rest.next() {
+        let mut value = || {
+            rest.next()
+                .cloned()
+                .ok_or_else(|| format!("Missing value for {flag}"))
+        };
+        if let Some(feature) = Feature::ALL.into_iter().find(|f| f.flag() == flag) {
+            opts.features.insert(feature);
+        }
+        match flag.as_str() {
+            "--clean" | "--detect-deployment" | "--qc" | "--snow" | "--thermal" => {}
+            "--resample" => opts.resampling.step = parse_minutes(flag, &value()?)?,
+            "--interpolation" => {
+                opts.resampling.interpolation = Interpolation::try_from(value()?.as_str())?;
+            }
+            "--max-gap" => opts.resampling.max_gap = parse_minutes(flag, &value()?)?,
+            "--soils" => opts.soil_files.push(value()?),
+            "--calib" => opts.calib_file = Some(value()?),
+            "--serial" => opts.serial = Some(value()?),
+            "--tz" => opts.vwc.read.output_tz = Some(parse_utc_offset(&value()?)?),
+            "--tz-offset" => opts.vwc.read.tz_offset = Some(parse_utc_offset(&value()?)?),
+            "--date-format" => {
+                opts.vwc.read.date_format = Some(TomstDateFormat::try_from(value()?.as_str())?);
+            }
+            "--decimal" => {
+                opts.vwc.read.decimal = Some(DecimalSeparator::try_from(value()?.as_str())?);
+            }
+            "--temp-sensor" => opts.temp_sensor = TempSensor::try_from(value()?.as_str())?,
+            "--ref-t" => opts.ref_t = Some(parse_number(flag, &value()?)?),
+            "--acor-t" => opts.acor_t = Some(parse_number(flag, &value()?)?),
+            "--wcor-t" => opts.wcor_t = Some(parse_number(flag, &value()?)?),
+            "--frozen" => opts.vwc.frozen = FrozenPolicy::try_from(value()?.as_str())?,
+            "--aggregate" => opts.agg.period = Period::try_from(value()?.as_str())?,
+            "--agg-fun" => {
+                opts.agg.functions = value()?
+                    .split(',')
+                    .map(|f| AggFunction::try_from(f.trim()))
+                    .collect::<Result<_, _>>()?;
+            }
+            "--indices" => opts.index.period = Period::try_from(value()?.as_str())?,
+            "--index-sensor" => opts.index.sensor = TempSensor::try_from(value()?.as_str())?,
+            "--gdd-base" => opts.index.gdd_base = parse_number(flag, &value()?)?,
+            "--fdd-base" => opts.index.fdd_base = parse_number(flag, &value()?)?,
+            "--drought" => opts.drought.threshold = parse_number(flag, &value()?)?,
+            "--drought-period" => opts.drought.period = Period::try_from(value()?.as_str())?,
+            "--min-coverage" => opts.agg.min_coverage = parse_number(flag, &value()?)?,
+            "--installed" => {
+                opts.vwc.deployment.installed = Some(parse_datetime(flag, &value()?)?);
+            }
+            "--removed" => opts.vwc.deployment.removed = Some(parse_datetime(flag, &value()?)?),
+            "--ensemble" => opts.ensemble = value()?,
+            "--ensemble-columns" => {
+                opts.ensemble_all = match value()?.to_lowercase().as_str() {
+                    "summary" => false,
+                    "all" => true,
+                    other => return Err(format!("Invalid --ensemble-columns value: {other}")),
+                };
+            }
+            "--sensor" => opts.sensor = Some(SensorFamily::try_from(value()?.as_str())?),
+            "--port" => {
+                opts.port = Some(
+                    value()?
+                        .parse()
+                        .map_err(|e| format!("Invalid --port value: {e}"))?,
+                );
+            }
+            "--potential" => opts.potential = Some(value()?),
+            "--texture" => opts.texture = Some(value()?),
+            "--soil" => opts.default_soil = Some(value()?),
+            "--per-locality" => opts.per_locality = true,
+            "--keep-going" => opts.keep_going = true,
+            "--out" => opts.out_dir = Some(PathBuf::from(value()?)),
+            "--threads" => {
+                opts.threads = value()?
+                    .parse()
+                    .map_err(|e| format!("Invalid --threads value: {e}"))?;
+            }
+            _ => return Err(format!("Unknown option: {flag}")),
+        }
+    }
+    Ok(opts)
+}
+
+impl Options {
+    fn has(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    /// Builtin and `--soils` soil types with the correction overrides applied.
+    fn registry(&self) -> Result<SoilRegistry, String> {
+        let mut registry = SoilRegistry::builtin();
+        for file in &self.soil_files {
+            registry
+                .extend_from_path(file)
+                .map_err(|e| format!("Failed to read soil table {file}: {e}"))?;
+        }
+        for soil in registry.params_mut() {
+            soil.ref_t = self.ref_t.unwrap_or(soil.ref_t);
+            soil.acor_t = self.acor_t.unwrap_or(soil.acor_t);
+            soil.wcor_t = self.wcor_t.unwrap_or(soil.wcor_t);
+        }
+        Ok(registry)
+    }
+
+    /// Retention curve of `--potential` for the texture of `--texture`, or
+    /// of `soil_name` when it is a builtin soil type.
+    fn hydraulic_model(&self, soil_name: Option<&str>) -> Result<Option<HydraulicModel>, String> {
+        let Some(model) = &self.potential else {
+            return Ok(None);
+        };
+        let texture = self
+            .texture
+            .as_deref()
+            .or(soil_name)
+            .ok_or("--potential needs --texture in batch mode")?;
+        let soil = SoilTypeModel::try_from(texture)
+            .map_err(|_| format!("--texture must be a builtin soil type, got {texture}"))?
+            .id;
+        let curve = match model.to_lowercase().as_str() {
+            "van-genuchten" | "vg" => HydraulicModel::van_genuchten(soil),
+            "campbell" => HydraulicModel::campbell(soil),
+            _ => return Err(format!("Unknown retention curve: {model}")),
+        };
+        curve
+            .map(Some)
+            .ok_or_else(|| format!("No {model} retention curve for {texture}"))
+    }
+
+    /// Soil types of `--ensemble`, with the correction overrides applied.
+    fn soil_ensemble(&self) -> Result<Option<SoilEnsemble>, String> {
+        if !self.has(Feature::Ensemble) {
+            return Ok(None);
+        }
+        let names: Vec<&str> = if self.ensemble.eq_ignore_ascii_case("tms4") {
+            TMS4_SOILS.iter().map(SoilType::as_str).collect()
+        } else {
+            self.ensemble.split(',').map(str::trim).collect()
+        };
+        let mut ensemble =
+            SoilEnsemble::from_registry(&self.registry()?, &names).map_err(|e| e.to_string())?;
+        ensemble.temp_sensor = self.temp_sensor;
+        Ok(Some(ensemble))
+    }
+
+    fn calibration_table(&self) -> Result<Option<CalibrationTable>, String> {
+        self.calib_file
+            .as_ref()
+            .map(|file| {
+                CalibrationTable::from_path(file)
+                    .map_err(|e| format!("Failed to read calibration table {file}: {e}"))
+            })
+            .transpose()
+    }
+}
+
+pub fn process_args(args: &[String]) -> Result<CliArgs, String> {
+    if args.len() < 3 {
+        return Err("Invalid number of arguments".to_string());
+    }
+
+    let input_file = args[1].clone();
+    let soil_name = &args[2];
+    let opts = parse_options(&args[3..])?;
+    if opts.default_soil.is_some() || opts.per_locality || opts.keep_going || opts.out_dir.is_some()
+    {
+        return Err(
+            "--soil, --per-locality, --keep-going and --out are only valid in batch mode"
+                .to_string(),
+        );
+    }
+
+    let hydraulics = opts.hydraulic_model(Some(soil_name))?;
+    let ensemble = opts.soil_ensemble()?;
+    let sensor = match opts.sensor {
+        Some(family) if family != SensorFamily::Tms => {
+            if let Some(feature) = opts.features.iter().find(|f| !f.any_sensor()) {
+                return Err(format!(
+                    "{} is only available for TMS loggers",
+                    feature.flag()
+                ));
+            }
+            if opts.calib_file.is_some() {
+                return Err("--calib is only available for TMS loggers".to_string());
+            }
+            if opts.vwc.read != TmsReadOptions::default() {
+                return Err(
+                    "--tz, --tz-offset, --date-format and --decimal are only available for TMS loggers"
+                        .to_string(),
+                );
+            }
+            let substrate = Substrate::try_from(soil_name.as_str())?;
+            Some(family.sensor_on_port(substrate, opts.port)?)
+        }
+        _ if opts.port.is_some() => {
+            return Err("--port is only available for other sensors than TMS loggers".to_string());
+        }
+        _ => None,
+    };
+    // The soil type of other sensors is their substrate
+    let soil = match opts.registry()?.resolve(soil_name) {
+        Ok(soil) => soil,
+        Err(_) if sensor.is_some() => VwcParams::default().soil,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            println!();
+            print_usage();
+
+            process::exit(1);
+        }
+    };
+
+    let mut options = opts.vwc.clone();
+    if let Some(table) = opts.calibration_table()? {
+        let serial = opts
+            .serial
+            .clone()
+            .or_else(|| logger_serial_from_path(&input_file))
+            .ok_or_else(|| format!("Cannot derive logger serial from {input_file}"))?;
+        options.calibration = table.schedule(&serial);
+        if options.calibration.is_empty() {
+            eprintln!(
+                "Warning: no calibration found for {serial} in {}",
+                opts.calib_file.as_deref().unwrap_or_default()
+            );
+        }
+    }
+
+    Ok(CliArgs {
+        input_file,
+        params: VwcParams {
+            soil,
+            temp_sensor: opts.temp_sensor,
+        },
+        options,
+        features: opts.features,
+        resampling: opts.resampling,
+        aggregate: opts.agg,
+        indices: opts.index,
+        drought: opts.drought,
+        ensemble,
+        ensemble_all: opts.ensemble_all,
+        sensor,
+        hydraulics,
+    })
+}
+
+pub fn process_batch_args(args: &[String]) -> Result<BatchArgs, String> {
+    if args.len() < 4 {
+        return Err("Invalid number of arguments".to_string());
+    }
+
+    let dir = args[2].clone();
+    let metadata = MetadataTable::from_path(&args[3])
+        .map_err(|e| format!("Failed to read metadata table {}: {e}", args[3]))?;
+    let opts = parse_options(&args[4..])?;
+    let mut single_only = opts
+        .features
+        .iter()
+        .filter(|f| !f.batch())
+        .map(|f| f.flag())
+        .chain(opts.serial.as_ref().map(|_| "--serial"))
+        .chain(opts.sensor.map(|_| "--sensor"))
+        .chain(opts.port.map(|_| "--port"));
+    if let Some(flag) = single_only.next() {
+        return Err(format!("{flag} is not available in batch mode"));
+    }
+
+    Ok(BatchArgs {
+        dir,
+        metadata,
+        options: BatchOptions {
+            vwc: opts.vwc.clone(),
+            calibration: opts.calibration_table()?,
+            registry: opts.registry()?,
+            default_soil: opts.default_soil.clone(),
+            temp_sensor: opts.temp_sensor,
+            detect_deployment: opts
+                .has(Feature::DetectDeployment)
+                .then(DetectOptions::default),
+            threads: opts.threads,
+        },
+        hydraulics: opts.hydraulic_model(None)?,
+        drought: opts.has(Feature::Drought).then(|| opts.drought.clone()),
+        per_locality: opts.per_locality,
+        keep_going: opts.keep_going,
+        out_dir: opts.out_dir.unwrap_or_else(|| PathBuf::from(".")),
+    })
+}
@@ -0,0 +1,316 @@
+/*
+ * Command line output
+ *
+ * Reports printed by the cleaning, deployment, snow and merge steps, and
+ * the CSV files written by each feature.
+ */
+
+use chrono::{NaiveDate, NaiveDateTime};
+use csv::{Writer, WriterBuilder};
+use soil_sensor_toolbox::{
+    climate_indices, mc_agg, thermal_diffusivity, AggOptions, AggVariable, CleanReport, Deployment,
+    DroughtOptions, DroughtStats, EnsembleRecord, FrozenPolicy, HydraulicModel, IndexOptions,
+    MergedLogger, SnowSummary, SoilEnsemble, ThermalOptions, TmsRecord, VwcOptions, VwcRecord,
+};
+use std::fs::File;
+use std::path::Path;
+
+pub fn print_clean_report(report: &CleanReport) {
+    println!("Cleaning report:");
+    println!("  start:        {}", report.start_date);
+    println!("  end:          {}", report.end_date);
+    println!("  step:         {} min", report.step_minutes);
+    println!("  duplicities:  {}", report.count_duplicities);
+    println!("  disordered:   {}", report.count_disordered);
+    println!("  rounded:      {}", report.count_rounded);
+    println!("  missing:      {}", report.count_missing);
+    for (from, to) in &report.gaps {
+        println!("  gap:          {from} -> {to}");
+    }
+}
+
+pub fn print_deployment(deployment: &Deployment) {
+    let show = |dt: Option<NaiveDateTime>| dt.map_or("not found".to_string(), |d| d.to_string());
+    println!("Deployment:");
+    println!("  installed:    {}", show(deployment.installed));
+    println!("  removed:      {}", show(deployment.removed));
+}
+
+pub fn print_snow_summary(summary: &SnowSummary) {
+    let show = |d: Option<NaiveDate>| d.map_or("none".to_string(), |d| d.to_string());
+    println!("Snow:");
+    println!("  snow days:    {:.1}", summary.snow_days);
+    println!("  first day:    {}", show(summary.first_day));
+    println!("  last day:     {}", show(summary.last_day));
+    if let (Some(start), Some(end)) = (summary.longest_start, summary.longest_end) {
+        println!(
+            "  longest:      {:.1} days, {start} -> {end}",
+            summary.longest_period
+        );
+    }
+}
+
+pub fn print_merge_report(logger: &MergedLogger) {
+    let report = &logger.report;
+    println!("Logger {}:", logger.serial);
+    println!("  files:        {}", report.sources);
+    println!("  read:         {}", report.count_read);
+    println!("  merged:       {}", logger.records.len());
+    println!("  duplicates:   {}", report.count_duplicates);
+    println!("  conflicts:    {}", report.conflicts.len());
+    for conflict in &report.conflicts {
+        println!(
+            "  conflict:     {} in {} differs from {}",
+            conflict.kept.datetime,
+            logger.files[conflict.dropped_source].display(),
+            logger.files[conflict.kept_source].display()
+        );
+    }
+    for reset in &report.clock_resets {
+        println!(
+            "  clock reset:  {} -> {} in {}",
+            reset.before,
+            reset.after,
+            logger.files[reset.source].display()
+        );
+        if reset.set_aside > 0 {
+            println!("  set aside:    {} records", reset.set_aside);
+        }
+    }
+}
+
+pub fn format_value(v: f64) -> String {
+    if v.is_nan() {
+        "NA".to_string()
+    } else {
+        format!("{v:.6}")
+    }
+}
+
+/// Columns written for every VWC record.
+pub struct RowFormat {
+    with_offset: bool,
+    flag_frozen: bool,
+    /// Names of the columns computed from the whole series
+    extra: Vec<&'static str>,
+    hydraulics: Option<HydraulicModel>,
+}
+
+impl RowFormat {
+    pub fn new(
+        options: &VwcOptions,
+        extra: Vec<&'static str>,
+        hydraulics: Option<HydraulicModel>,
+    ) -> Self {
+        Self {
+            with_offset: options.read.tz_offset.is_some() || options.read.output_tz.is_some(),
+            flag_frozen: options.frozen == FrozenPolicy::Flag,
+            extra,
+            hydraulics,
+        }
+    }
+
+    pub fn header(&self, prefix: &[&str]) -> Vec<String> {
+        let mut header: Vec<String> = prefix.iter().map(ToString::to_string).collect();
+        header.push("datetime".to_string());
+        if self.with_offset {
+            header.push("utc_offset".to_string());
+        }
+        header.extend(["raw", "temp", "VWC_moisture"].map(String::from));
+        if self.hydraulics.is_some() {
+            header.extend(["potential_kPa", "REW"].map(String::from));
+        }
+        if self.flag_frozen {
+            header.push("frozen".to_string());
+        }
+        header.extend(self.extra.iter().map(ToString::to_string));
+        header
+    }
+
+    pub fn row(&self, prefix: &[&str], rec: &VwcRecord, extra: &[String]) -> Vec<String> {
+        let mut row: Vec<String> = prefix.iter().map(ToString::to_string).collect();
+        row.push(rec.datetime.format("%Y.%m.%d %H:%M").to_string());
+        if self.with_offset {
+            row.push(rec.utc_offset().to_string());
+        }
+        row.extend([
+            rec.raw.to_string(),
+            rec.temp.to_string(),
+            format_value(rec.vwc),
+        ]);
+        if let Some(model) = &self.hydraulics {
+            row.extend([
+                format_value(model.potential(rec.vwc)),
+                format_value(model.rew(rec.vwc)),
+            ]);
+        }
+        if self.flag_frozen {
+            row.push(u8::from(rec.frozen).to_string());
+        }
+        row.extend_from_slice(extra);
+        row
+    }
+}
+
+pub fn csv_writer<P: AsRef<Path>>(path: P) -> csv::Result<Writer<File>> {
+    WriterBuilder::new().delimiter(b';').from_path(path)
+}
+
+pub fn write_indices(
+    records: &[TmsRecord],
+    options: &IndexOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = format!("indices_{}.csv", options.period.as_str());
+    let mut wtr = csv_writer(&path)?;
+    wtr.write_record(["datetime", "GDD", "FDD", "frost_days", "freeze_thaw_cycles"])?;
+    for row in climate_indices(records, options) {
+        wtr.write_record([
+            row.start.format("%Y.%m.%d %H:%M").to_string(),
+            format_value(row.gdd),
+            format_value(row.fdd),
+            row.frost_days.to_string(),
+            row.freeze_thaw_cycles.to_string(),
+        ])?;
+    }
+    wtr.flush()?;
+    println!("wrote {path}");
+    Ok(())
+}
+
+/// VWC summary of the soil ensemble, with the VWC of every soil type
+/// when `all` is set.
+pub fn write_ensemble(
+    records: &[EnsembleRecord],
+    ensemble: &SoilEnsemble,
+    all: bool,
+    options: &VwcOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let with_offset = options.read.tz_offset.is_some() || options.read.output_tz.is_some();
+    let path = "ensemble.csv";
+    let mut wtr = csv_writer(path)?;
+    let mut header = vec!["datetime".to_string()];
+    if with_offset {
+        header.push("utc_offset".to_string());
+    }
+    header.extend(["raw", "temp"].map(String::from));
+    if all {
+        header.extend(ensemble.names().map(|name| format!("VWC_{name}")));
+    }
+    header.extend(["VWC_min", "VWC_median", "VWC_max", "VWC_spread"].map(String::from));
+    wtr.write_record(&header)?;
+    for rec in records {
+        let mut row = vec![rec.datetime.format("%Y.%m.%d %H:%M").to_string()];
+        if with_offset {
+            row.push(rec.utc_offset().to_string());
+        }
+        row.extend([rec.raw.to_string(), rec.temp.to_string()]);
+        if all {
+            row.extend(rec.vwc.iter().map(|v| format_value(*v)));
+        }
+        row.extend([rec.min, rec.median, rec.max, rec.spread].map(format_value));
+        wtr.write_record(&row)?;
+    }
+    wtr.flush()?;
+    println!("wrote {path}");
+    Ok(())
+}
+
+/// Daily thermal diffusivity, in mm²/s (10⁻⁶ m²/s).
+pub fn write_thermal(
+    records: &[TmsRecord],
+    options: &ThermalOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let path = "thermal.csv";
+    let mut wtr = csv_writer(path)?;
+    wtr.write_record([
+        "date",
+        "amplitude_T2",
+        "amplitude_T1",
+        "phase_lag_h",
+        "D_amplitude_mm2_s",
+        "D_phase_mm2_s",
+    ])?;
+    for day in thermal_diffusivity(records, options) {
+        wtr.write_record([
+            day.date.format("%Y.%m.%d").to_string(),
+            format_value(day.amplitude_surface),
+            format_value(day.amplitude_soil),
+            format_value(day.phase_lag),
+            format_value(day.amplitude * 1e6),
+            format_value(day.phase * 1e6),
+        ])?;
+    }
+    wtr.flush()?;
+    println!("wrote {path}");
+    Ok(())
+}
+
+/// Drought statistics of every series, each row starting with the values
+/// of the `prefix` columns.
+pub fn write_drought(
+    path: &Path,
+    prefix: &[&str],
+    series: &[(Vec<String>, Vec<DroughtStats>)],
+    options: &DroughtOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut wtr = csv_writer(path)?;
+    let mut header: Vec<String> = prefix.iter().map(ToString::to_string).collect();
+    header.extend(
+        [
+            "datetime",
+            "days",
+            "days_below",
+            "duration_below",
+            "longest_dry_spell",
+            "dry_spell_start",
+        ]
+        .map(String::from),
+    );
+    header.extend(options.percentiles.iter().map(|p| format!("p{p}")));
+    wtr.write_record(&header)?;
+    for (values, periods) in series {
+        for stats in periods {
+            let mut row = values.clone();
+            row.extend([
+                stats.start.format("%Y.%m.%d %H:%M").to_string(),
+                stats.days.to_string(),
+                stats.days_below.to_string(),
+                format_value(stats.duration_below),
+                stats.longest_dry_spell.to_string(),
+                stats
+                    .dry_spell_start
+                    .map_or("NA".to_string(), |d| d.to_string()),
+            ]);
+            row.extend(stats.percentiles.iter().map(|p| format_value(*p)));
+            wtr.write_record(&row)?;
+        }
+    }
+    wtr.flush()?;
+    println!("wrote {}", path.display());
+    Ok(())
+}
+
+pub fn write_aggregated(
+    records: &[VwcRecord],
+    options: &AggOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let variables: [AggVariable<'_, VwcRecord>; 3] = [
+        ("raw", &|r| r.raw),
+        ("temp", &|r| r.temp),
+        ("VWC_moisture", &|r| r.vwc),
+    ];
+    let table = mc_agg(records, &variables, options)?;
+    let path = format!("output_{}.csv", options.period.as_str());
+    let mut wtr = csv_writer(&path)?;
+    let mut header = vec!["datetime".to_string()];
+    header.extend(table.columns.iter().cloned());
+    wtr.write_record(&header)?;
+    for row in &table.rows {
+        let mut out = vec![row.start.format("%Y.%m.%d %H:%M").to_string()];
+        out.extend(row.values.iter().map(|v| format_value(*v)));
+        wtr.write_record(&out)?;
+    }
+    wtr.flush()?;
+    println!("wrote {path}");
+    Ok(())
+}
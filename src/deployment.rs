@@ -0,0 +1,213 @@
+/*
+ * Deployment Periods
+ *
+ * Trimming of logger time series to the period the logger spent in the
+ * soil, with the installation and removal moments given or detected from
+ * the moisture count and soil temperature.
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+#![allow(clippy::cast_precision_loss)]
+
+use crate::clean::{detect_step, TimeSeriesRecord};
+use crate::tomst::TmsRecord;
+use chrono::{NaiveDateTime, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+/// Moisture count up to which a TMS sensor reads air rather than soil.
+pub const AIR_MOISTURE_COUNT: f64 = 500.0;
+
+/// Period a logger spent in the soil. Open ends keep all records on that
+/// side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deployment {
+    /// First datetime in the soil
+    pub installed: Option<NaiveDateTime>,
+    /// Last datetime in the soil
+    pub removed: Option<NaiveDateTime>,
+}
+
+impl Deployment {
+    #[must_use]
+    pub fn contains(&self, datetime: NaiveDateTime) -> bool {
+        self.installed.is_none_or(|i| datetime >= i) && self.removed.is_none_or(|r| datetime <= r)
+    }
+
+    #[must_use]
+    pub fn is_open(&self) -> bool {
+        self.installed.is_none() && self.removed.is_none()
+    }
+
+    /// Fill the missing ends from `other`.
+    #[must_use]
+    pub fn or(self, other: Deployment) -> Deployment {
+        Deployment {
+            installed: self.installed.or(other.installed),
+            removed: self.removed.or(other.removed),
+        }
+    }
+}
+
+/// Records of `records` within `deployment`.
+#[must_use]
+pub fn trim_to_deployment<T: TimeSeriesRecord>(records: &[T], deployment: &Deployment) -> Vec<T> {
+    records
+        .iter()
+        .filter(|r| deployment.contains(r.datetime()))
+        .cloned()
+        .collect()
+}
+
+/// Options of [`detect_deployment`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DetectOptions {
+    /// Length of the periods compared before and after a candidate moment
+    pub window: TimeDelta,
+    /// Minimal change of the mean moisture count between the two periods
+    pub min_moisture_jump: f64,
+    /// Maximal mean moisture count of the period out of the soil, which
+    /// tells the logger handling apart from freezing and thawing
+    pub max_air_moisture: f64,
+    /// Minimal ratio between the larger and the smaller T1 variance of the
+    /// two periods
+    pub min_variance_ratio: f64,
+}
+
+impl Default for DetectOptions {
+    fn default() -> Self {
+        Self {
+            window: TimeDelta::days(1),
+            min_moisture_jump: 300.0,
+            max_air_moisture: AIR_MOISTURE_COUNT,
+            min_variance_ratio: 2.0,
+        }
+    }
+}
+
+/// Prefix sums of the non-missing values of a series.
+struct Prefix {
+    sum: Vec<f64>,
+    sum_sq: Vec<f64>,
+    count: Vec<usize>,
+}
+
+impl Prefix {
+    fn new(values: impl Iterator<Item = f64>) -> Self {
+        let mut p = Prefix {
+            sum: vec![0.0],
+            sum_sq: vec![0.0],
+            count: vec![0],
+        };
+        for v in values {
+            let (s, q, c) = (
+                p.sum[p.sum.len() - 1],
+                p.sum_sq[p.sum_sq.len() - 1],
+                p.count[p.count.len() - 1],
+            );
+            if v.is_nan() {
+                p.sum.push(s);
+                p.sum_sq.push(q);
+                p.count.push(c);
+            } else {
+                p.sum.push(s + v);
+                p.sum_sq.push(q + v * v);
+                p.count.push(c + 1);
+            }
+        }
+        p
+    }
+
+    /// Mean and variance of the values in `from..to`.
+    fn stats(&self, from: usize, to: usize) -> Option<(f64, f64)> {
+        let n = self.count[to] - self.count[from];
+        if n < 2 {
+            return None;
+        }
+        let n = n as f64;
+        let mean = (self.sum[to] - self.sum[from]) / n;
+        let var = ((self.sum_sq[to] - self.sum_sq[from]) / n - mean * mean).max(0.0);
+        Some((mean, var))
+    }
+}
+
+/// Strongest moisture change at a record index `i` (between `i - w..i` and
+/// `i..i + w`) in the direction of `sign`, among the candidates in `range`
+/// that also show a change of the temperature variance.
+fn strongest_change(
+    moisture: &Prefix,
+    temp: &Prefix,
+    w: usize,
+    range: std::ops::Range<usize>,
+    sign: f64,
+    options: &DetectOptions,
+) -> Option<usize> {
+    let mut best: Option<(usize, f64)> = None;
+    for i in range {
+        let (Some((m_before, _)), Some((m_after, _))) =
+            (moisture.stats(i - w, i), moisture.stats(i, i + w))
+        else {
+            continue;
+        };
+        let jump = sign * (m_after - m_before);
+        let air = if sign > 0.0 { m_before } else { m_after };
+        if jump < options.min_moisture_jump || air > options.max_air_moisture {
+            continue;
+        }
+        let (Some((_, v_before)), Some((_, v_after))) =
+            (temp.stats(i - w, i), temp.stats(i, i + w))
+        else {
+            continue;
+        };
+        let ratio = v_before.max(v_after) / v_before.min(v_after).max(1e-9);
+        if ratio < options.min_variance_ratio {
+            continue;
+        }
+        if best.is_none_or(|(_, b)| jump > b) {
+            best = Some((i, jump));
+        }
+    }
+    best.map(|(i, _)| i)
+}
+
+/// Detect when a TMS logger was put in and taken out of the soil.
+///
+/// Installation is the moment of the largest rise of the mean moisture count
+/// from the level of air between the `window` before and after it, removal
+/// the largest drop to that level after installation. Both also require the
+/// variance of the soil temperature (T1) to change, as the soil damps the
+/// temperature variations of the air.
+/// Records are expected in time order with a regular step.
+#[must_use]
+pub fn detect_deployment(records: &[TmsRecord], options: &DetectOptions) -> Deployment {
+    let datetimes: Vec<NaiveDateTime> = records.iter().map(|r| r.datetime).collect();
+    let Some(step) = detect_step(&datetimes) else {
+        return Deployment::default();
+    };
+    let w = usize::try_from(options.window.num_seconds() / step.num_seconds().max(1))
+        .unwrap_or(0)
+        .max(2);
+    if records.len() < 2 * w {
+        return Deployment::default();
+    }
+    let moisture = Prefix::new(records.iter().map(|r| r.moisture));
+    let temp = Prefix::new(records.iter().map(|r| r.t1));
+    let candidates = w..records.len() - w + 1;
+
+    let installed = strongest_change(&moisture, &temp, w, candidates.clone(), 1.0, options);
+    let from = installed.map_or(candidates.start, |i| (i + w).min(candidates.end));
+    let removed = strongest_change(&moisture, &temp, w, from..candidates.end, -1.0, options);
+    Deployment {
+        installed: installed.map(|i| records[i].datetime),
+        // Last record before the drop
+        removed: removed.map(|i| records[i - 1].datetime),
+    }
+}
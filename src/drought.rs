@@ -0,0 +1,132 @@
+/*
+ * Drought Statistics
+ *
+ * Moisture threshold statistics of VWC series per aggregation period: time
+ * and days below a VWC threshold, longest dry spell and VWC percentiles,
+ * to compare the water regime of sites.
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+#![allow(clippy::cast_precision_loss)]
+
+use crate::aggregate::{group_by_period, quantile, Period};
+use crate::clean::detect_step;
+use crate::vwc::VwcRecord;
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Parameters of [`drought_stats`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DroughtOptions {
+    /// VWC (m³/m³) below which the soil is dry
+    pub threshold: f64,
+    pub period: Period,
+    /// Percentiles (0 to 100) of the VWC
+    pub percentiles: Vec<f64>,
+}
+
+impl Default for DroughtOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 0.1,
+            period: Period::Month,
+            percentiles: vec![5.0, 25.0, 50.0, 75.0, 95.0],
+        }
+    }
+}
+
+/// Drought statistics of one period.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DroughtStats {
+    pub start: NaiveDateTime,
+    /// Exclusive end of the period
+    pub end: NaiveDateTime,
+    /// Days with at least one VWC value
+    pub days: usize,
+    /// Days with a mean VWC below the threshold
+    pub days_below: usize,
+    /// Time with a VWC below the threshold, in days
+    pub duration_below: f64,
+    /// Longest run of consecutive days below the threshold
+    pub longest_dry_spell: usize,
+    /// First day of the longest dry spell
+    pub dry_spell_start: Option<NaiveDate>,
+    /// VWC percentiles, in the order of [`DroughtOptions::percentiles`]
+    pub percentiles: Vec<f64>,
+}
+
+/// Drought statistics of the output of [`crate::process_file`] per period
+/// of `options.period`.
+///
+/// A day counts as dry when its mean VWC is below the threshold; days
+/// without values interrupt a dry spell, and spells are cut at period
+/// boundaries. Missing values are ignored. Records are expected in time
+/// order.
+#[must_use]
+pub fn drought_stats(records: &[VwcRecord], options: &DroughtOptions) -> Vec<DroughtStats> {
+    let datetimes: Vec<NaiveDateTime> = records.iter().map(|r| r.datetime).collect();
+    let step = detect_step(&datetimes);
+    let step_days = step.map_or(0.0, |s| s.num_seconds() as f64 / 86_400.0);
+
+    group_by_period(records, options.period, step)
+        .into_iter()
+        .map(|(start, end, members)| {
+            let values: Vec<f64> = members
+                .iter()
+                .map(|r| r.vwc)
+                .filter(|v| !v.is_nan())
+                .collect();
+
+            // Sum and count of the values of every day
+            let mut daily: BTreeMap<NaiveDate, (f64, usize)> = BTreeMap::new();
+            for r in members.iter().filter(|r| !r.vwc.is_nan()) {
+                let day = daily.entry(r.datetime.date()).or_default();
+                *day = (day.0 + r.vwc, day.1 + 1);
+            }
+            let dry: Vec<NaiveDate> = daily
+                .iter()
+                .filter(|(_, (sum, n))| sum / (*n as f64) < options.threshold)
+                .map(|(date, _)| *date)
+                .collect();
+
+            let mut longest: Option<(NaiveDate, usize)> = None;
+            let mut run: Option<(NaiveDate, usize)> = None;
+            for (i, date) in dry.iter().enumerate() {
+                let follows = i > 0 && date.pred_opt() == Some(dry[i - 1]);
+                run = match run {
+                    Some((first, n)) if follows => Some((first, n + 1)),
+                    _ => Some((*date, 1)),
+                };
+                if longest.is_none_or(|(_, n)| run.is_some_and(|(_, m)| m > n)) {
+                    longest = run;
+                }
+            }
+
+            DroughtStats {
+                start,
+                end,
+                days: daily.len(),
+                days_below: dry.len(),
+                duration_below: values.iter().filter(|v| **v < options.threshold).count() as f64
+                    * step_days,
+                longest_dry_spell: longest.map_or(0, |(_, n)| n),
+                dry_spell_start: longest.map(|(first, _)| first),
+                percentiles: options
+                    .percentiles
+                    .iter()
+                    .map(|p| quantile(&values, p / 100.0))
+                    .collect(),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,173 @@
+/*
+ * Soil Type Ensemble
+ *
+ * VWC of a TMS series for several soil calibration curves at once, with
+ * per-record summary statistics, to assess the uncertainty coming from a
+ * poorly known soil texture.
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+use crate::aggregate::quantile;
+use crate::soil::{SoilParams, SoilRegistry};
+use crate::tomst::{tz_code_offset, TmsRecord};
+use crate::vwc::{SoilType, TempSensor, VwcOptions, VwcParams, VwcRecord};
+use anyhow::{bail, Result};
+use chrono::{FixedOffset, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+/// Soil types calibrated for the TMS4 sensor, the default ensemble. Water
+/// and the TMS1 curves are left out.
+pub const TMS4_SOILS: [SoilType; 9] = [
+    SoilType::Sand,
+    SoilType::LoamySandA,
+    SoilType::LoamySandB,
+    SoilType::SandyLoamA,
+    SoilType::SandyLoamB,
+    SoilType::Loam,
+    SoilType::SiltLoam,
+    SoilType::Peat,
+    SoilType::Universal,
+];
+
+/// Named soil curves evaluated together.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SoilEnsemble {
+    pub members: Vec<(String, SoilParams)>,
+    /// Temperature used for the correction of every member
+    pub temp_sensor: TempSensor,
+}
+
+impl Default for SoilEnsemble {
+    fn default() -> Self {
+        Self::tms4()
+    }
+}
+
+impl SoilEnsemble {
+    /// Ensemble of [`TMS4_SOILS`].
+    #[must_use]
+    pub fn tms4() -> Self {
+        Self {
+            members: TMS4_SOILS
+                .iter()
+                .map(|s| (s.as_str().to_string(), SoilParams::from(*s)))
+                .collect(),
+            temp_sensor: TempSensor::default(),
+        }
+    }
+
+    /// Ensemble of the soils `names` of `registry`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `names` is empty or a soil is not registered.
+    pub fn from_registry<S: AsRef<str>>(registry: &SoilRegistry, names: &[S]) -> Result<Self> {
+        if names.is_empty() {
+            bail!("Empty soil ensemble");
+        }
+        let members = names
+            .iter()
+            .map(|name| {
+                let name = name.as_ref();
+                registry
+                    .resolve(name)
+                    .map(|params| (name.to_lowercase(), params))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            members,
+            temp_sensor: TempSensor::default(),
+        })
+    }
+
+    /// Names of the members, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.members.iter().map(|(name, _)| name.as_str())
+    }
+}
+
+/// A TMS record with the VWC of every ensemble member.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnsembleRecord {
+    pub datetime: NaiveDateTime,
+    /// Time zone code of `datetime` (offset in 15 minute units)
+    pub timezone: i32,
+    /// Raw moisture count
+    pub raw: f64,
+    /// Temperature (°C) used for the temperature correction
+    pub temp: f64,
+    /// VWC of every member, in the order of [`SoilEnsemble::members`]
+    pub vwc: Vec<f64>,
+    /// Smallest, median and largest VWC of the members, `NaN` when missing
+    pub min: f64,
+    pub median: f64,
+    pub max: f64,
+    /// Difference between the largest and smallest VWC
+    pub spread: f64,
+    /// Soil temperature below [`crate::FROZEN_T`]
+    pub frozen: bool,
+}
+
+impl EnsembleRecord {
+    /// UTC offset of `datetime`.
+    #[must_use]
+    pub fn utc_offset(&self) -> FixedOffset {
+        tz_code_offset(self.timezone)
+    }
+
+    /// VWC of a single TMS record for every member of `ensemble`, with the
+    /// calibration and frozen soil policy of `options`.
+    #[must_use]
+    pub fn from_tms(rec: &TmsRecord, ensemble: &SoilEnsemble, options: &VwcOptions) -> Self {
+        let members: Vec<VwcRecord> = ensemble
+            .members
+            .iter()
+            .map(|(_, soil)| {
+                let params = VwcParams {
+                    soil: *soil,
+                    temp_sensor: ensemble.temp_sensor,
+                };
+                VwcRecord::from_tms(rec, params, options)
+            })
+            .collect();
+        let vwc: Vec<f64> = members.iter().map(|m| m.vwc).collect();
+        let values = vwc.iter().copied().filter(|v| !v.is_nan());
+        let (min, max) = values.fold((f64::NAN, f64::NAN), |(lo, hi), v| (v.min(lo), v.max(hi)));
+        Self {
+            datetime: rec.datetime,
+            timezone: rec.timezone,
+            raw: rec.moisture,
+            temp: ensemble.temp_sensor.value(rec),
+            median: quantile(&vwc, 0.5),
+            min,
+            max,
+            spread: max - min,
+            frozen: members.first().is_some_and(|m| m.frozen),
+            vwc,
+        }
+    }
+}
+
+/// Compute the VWC of every member of `ensemble` for TMS records in a single
+/// pass, dropping the records outside the deployment of `options`.
+#[must_use]
+pub fn process_ensemble(
+    records: &[TmsRecord],
+    ensemble: &SoilEnsemble,
+    options: &VwcOptions,
+) -> Vec<EnsembleRecord> {
+    records
+        .iter()
+        .filter(|rec| options.deployment.contains(rec.datetime))
+        .map(|rec| EnsembleRecord::from_tms(rec, ensemble, options))
+        .collect()
+}
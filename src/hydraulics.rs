@@ -0,0 +1,233 @@
+/*
+ * Soil Hydraulics
+ *
+ * Conversion of volumetric water content to matric potential with the
+ * van Genuchten (1980) or Campbell (1974) water retention curves, and
+ * relative extractable water between field capacity and wilting point.
+ *
+ * Default parameters per texture class:
+ * - van Genuchten: Carsel & Parrish (1988), 10.1029/WR024i005p00755
+ * - Campbell: Clapp & Hornberger (1978), 10.1029/WR014i004p00601
+ * - Peat: Letts et al. (2000), 10.1080/07055900.2000.9649648 (Campbell only,
+ *   there are no default van Genuchten parameters for peat)
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+use crate::vwc::{SoilType, VwcRecord};
+use serde::{Deserialize, Serialize};
+
+/// Pressure of 1 cm of water column (kPa).
+pub const KPA_PER_CM: f64 = 0.098_066_5;
+/// Matric potential at field capacity (kPa).
+pub const FIELD_CAPACITY_KPA: f64 = -33.0;
+/// Matric potential at the permanent wilting point (kPa).
+pub const WILTING_POINT_KPA: f64 = -1500.0;
+
+/// van Genuchten (1980) water retention curve, with `m = 1 - 1/n`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VanGenuchten {
+    /// Residual water content (m³/m³)
+    pub theta_r: f64,
+    /// Saturated water content (m³/m³)
+    pub theta_s: f64,
+    /// Inverse of the air entry suction (1/cm of water)
+    pub alpha: f64,
+    /// Pore size distribution parameter (> 1)
+    pub n: f64,
+}
+
+impl VanGenuchten {
+    /// Matric potential (kPa, ≤ 0) at `vwc`, 0 at saturation and `NaN` at or
+    /// below the residual water content.
+    #[must_use]
+    pub fn potential(&self, vwc: f64) -> f64 {
+        let se = (vwc - self.theta_r) / (self.theta_s - self.theta_r);
+        if se <= 0.0 || se.is_nan() {
+            return f64::NAN;
+        }
+        if se >= 1.0 {
+            return 0.0;
+        }
+        let m = 1.0 - 1.0 / self.n;
+        let h = (se.powf(-1.0 / m) - 1.0).powf(1.0 / self.n) / self.alpha;
+        -h * KPA_PER_CM
+    }
+
+    /// Water content (m³/m³) at the matric potential `psi` (kPa).
+    #[must_use]
+    pub fn vwc(&self, psi: f64) -> f64 {
+        let h = (psi.min(0.0) / KPA_PER_CM).abs();
+        let m = 1.0 - 1.0 / self.n;
+        self.theta_r + (self.theta_s - self.theta_r) / (1.0 + (self.alpha * h).powf(self.n)).powf(m)
+    }
+}
+
+/// Campbell (1974) water retention curve `psi = psi_e · (θ/θs)^-b`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Campbell {
+    /// Saturated water content (m³/m³)
+    pub theta_s: f64,
+    /// Air entry potential (kPa, < 0)
+    pub psi_e: f64,
+    /// Pore size distribution exponent
+    pub b: f64,
+}
+
+impl Campbell {
+    /// Matric potential (kPa, ≤ 0) at `vwc`, the air entry potential from
+    /// saturation on and `NaN` for a dry soil.
+    #[must_use]
+    pub fn potential(&self, vwc: f64) -> f64 {
+        if vwc <= 0.0 || vwc.is_nan() {
+            return f64::NAN;
+        }
+        self.psi_e * (vwc / self.theta_s).min(1.0).powf(-self.b)
+    }
+
+    /// Water content (m³/m³) at the matric potential `psi` (kPa).
+    #[must_use]
+    pub fn vwc(&self, psi: f64) -> f64 {
+        if psi >= self.psi_e {
+            return self.theta_s;
+        }
+        self.theta_s * (psi / self.psi_e).powf(-1.0 / self.b)
+    }
+}
+
+/// Water retention curve of a soil.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum HydraulicModel {
+    VanGenuchten(VanGenuchten),
+    Campbell(Campbell),
+}
+
+/// Texture classes with default hydraulic parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Texture {
+    Sand,
+    LoamySand,
+    SandyLoam,
+    Loam,
+    SiltLoam,
+    Peat,
+}
+
+impl Texture {
+    /// Texture of the soil types of myClim; the universal curve is taken as
+    /// a loam. Water has no retention curve.
+    fn of(soil: SoilType) -> Option<Self> {
+        match soil {
+            SoilType::Sand | SoilType::SandTMS1 => Some(Texture::Sand),
+            SoilType::LoamySandA | SoilType::LoamySandB | SoilType::LoamySandTMS1 => {
+                Some(Texture::LoamySand)
+            }
+            SoilType::SandyLoamA | SoilType::SandyLoamB => Some(Texture::SandyLoam),
+            SoilType::Loam | SoilType::Universal => Some(Texture::Loam),
+            SoilType::SiltLoam | SoilType::SiltLoamTMS1 => Some(Texture::SiltLoam),
+            SoilType::Peat => Some(Texture::Peat),
+            SoilType::Water => None,
+        }
+    }
+}
+
+impl HydraulicModel {
+    /// Default van Genuchten parameters of the texture of `soil`, `None`
+    /// for water and peat, see [`Self::campbell`] for peat.
+    #[must_use]
+    pub fn van_genuchten(soil: SoilType) -> Option<Self> {
+        let (theta_r, theta_s, alpha, n) = match Texture::of(soil)? {
+            Texture::Sand => (0.045, 0.43, 0.145, 2.68),
+            Texture::LoamySand => (0.057, 0.41, 0.124, 2.28),
+            Texture::SandyLoam => (0.065, 0.41, 0.075, 1.89),
+            Texture::Loam => (0.078, 0.43, 0.036, 1.56),
+            Texture::SiltLoam => (0.067, 0.45, 0.020, 1.41),
+            Texture::Peat => return None,
+        };
+        Some(HydraulicModel::VanGenuchten(VanGenuchten {
+            theta_r,
+            theta_s,
+            alpha,
+            n,
+        }))
+    }
+
+    /// Default Campbell parameters of the texture of `soil`, `None` for
+    /// water.
+    #[must_use]
+    pub fn campbell(soil: SoilType) -> Option<Self> {
+        // Air entry potential in cm of water
+        let (theta_s, psi_e_cm, b) = match Texture::of(soil)? {
+            Texture::Sand => (0.395, -12.1, 4.05),
+            Texture::LoamySand => (0.410, -9.0, 4.38),
+            Texture::SandyLoam => (0.435, -21.8, 4.90),
+            Texture::Loam => (0.451, -47.8, 5.39),
+            Texture::SiltLoam => (0.485, -78.6, 5.30),
+            Texture::Peat => (0.93, -1.03, 2.7),
+        };
+        Some(HydraulicModel::Campbell(Campbell {
+            theta_s,
+            psi_e: psi_e_cm * KPA_PER_CM,
+            b,
+        }))
+    }
+
+    /// Matric potential (kPa) at `vwc`.
+    #[must_use]
+    pub fn potential(&self, vwc: f64) -> f64 {
+        match self {
+            HydraulicModel::VanGenuchten(m) => m.potential(vwc),
+            HydraulicModel::Campbell(m) => m.potential(vwc),
+        }
+    }
+
+    /// Water content (m³/m³) at the matric potential `psi` (kPa).
+    #[must_use]
+    pub fn vwc(&self, psi: f64) -> f64 {
+        match self {
+            HydraulicModel::VanGenuchten(m) => m.vwc(psi),
+            HydraulicModel::Campbell(m) => m.vwc(psi),
+        }
+    }
+
+    /// Water content at field capacity ([`FIELD_CAPACITY_KPA`]).
+    #[must_use]
+    pub fn field_capacity(&self) -> f64 {
+        self.vwc(FIELD_CAPACITY_KPA)
+    }
+
+    /// Water content at the permanent wilting point ([`WILTING_POINT_KPA`]).
+    #[must_use]
+    pub fn wilting_point(&self) -> f64 {
+        self.vwc(WILTING_POINT_KPA)
+    }
+
+    /// Relative extractable water at `vwc`, see
+    /// [`relative_extractable_water`].
+    #[must_use]
+    pub fn rew(&self, vwc: f64) -> f64 {
+        relative_extractable_water(vwc, self.field_capacity(), self.wilting_point())
+    }
+}
+
+/// Relative extractable water `(θ - θwp) / (θfc - θwp)`: 0 at the wilting
+/// point, 1 at field capacity. Not bounded, wetter soils exceed 1.
+#[must_use]
+pub fn relative_extractable_water(vwc: f64, field_capacity: f64, wilting_point: f64) -> f64 {
+    (vwc - wilting_point) / (field_capacity - wilting_point)
+}
+
+/// Matric potential (kPa) of every record of the output of
+/// [`crate::mc_calc_vwc`].
+#[must_use]
+pub fn water_potential(records: &[VwcRecord], model: &HydraulicModel) -> Vec<f64> {
+    records.iter().map(|r| model.potential(r.vwc)).collect()
+}
@@ -0,0 +1,176 @@
+/*
+ * Climate Indices
+ *
+ * Thermal indices of TMS temperature series: growing and freezing degree
+ * days (myClim `mc_calc_gdd` and `mc_calc_fdd`), frost days and
+ * freeze-thaw cycles, per aggregation period.
+ * Original myClim package: https://github.com/ibot-geoecology/myClim
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+#![allow(clippy::cast_precision_loss)]
+
+use crate::aggregate::{group_by_period, Period};
+use crate::clean::detect_step;
+use crate::tomst::TmsRecord;
+use crate::vwc::TempSensor;
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Length of `step` in days.
+fn step_days(step: TimeDelta) -> f64 {
+    step.num_seconds() as f64 / 86_400.0
+}
+
+/// Degree days of every record above `t_base` (myClim `mc_calc_gdd`): the
+/// excess of the temperature of `sensor` over `t_base` times the logging
+/// step in days. Missing temperatures give `NaN`.
+#[must_use]
+pub fn mc_calc_gdd(records: &[TmsRecord], sensor: TempSensor, t_base: f64) -> Vec<f64> {
+    degree_days(records, |r| sensor.value(r) - t_base)
+}
+
+/// Degree days of every record below `t_base` (myClim `mc_calc_fdd`), as
+/// positive values.
+#[must_use]
+pub fn mc_calc_fdd(records: &[TmsRecord], sensor: TempSensor, t_base: f64) -> Vec<f64> {
+    degree_days(records, |r| t_base - sensor.value(r))
+}
+
+fn degree_days(records: &[TmsRecord], excess: impl Fn(&TmsRecord) -> f64) -> Vec<f64> {
+    let datetimes: Vec<NaiveDateTime> = records.iter().map(|r| r.datetime).collect();
+    let days = detect_step(&datetimes).map_or(0.0, step_days);
+    records
+        .iter()
+        .map(|r| {
+            let e = excess(r);
+            if e.is_nan() {
+                f64::NAN
+            } else {
+                e.max(0.0) * days
+            }
+        })
+        .collect()
+}
+
+/// Parameters of [`climate_indices`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct IndexOptions {
+    /// Sensor of the temperature (default: T3, air)
+    pub sensor: TempSensor,
+    pub period: Period,
+    /// Base temperature of the growing degree days (°C)
+    pub gdd_base: f64,
+    /// Base temperature of the freezing degree days (°C)
+    pub fdd_base: f64,
+    /// A day is a frost day when its minimum is below this temperature (°C)
+    pub frost_threshold: f64,
+    /// The temperature has to go this far below and then above 0 °C to
+    /// count a freeze-thaw cycle, to ignore the sensor noise around 0 °C
+    pub freeze_thaw_margin: f64,
+}
+
+impl Default for IndexOptions {
+    fn default() -> Self {
+        Self {
+            sensor: TempSensor::T3,
+            period: Period::Month,
+            gdd_base: 5.0,
+            fdd_base: 0.0,
+            frost_threshold: 0.0,
+            freeze_thaw_margin: 0.0,
+        }
+    }
+}
+
+/// Thermal indices of one period.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ClimateIndices {
+    pub start: NaiveDateTime,
+    /// Exclusive end of the period
+    pub end: NaiveDateTime,
+    /// Growing degree days (°C·day)
+    pub gdd: f64,
+    /// Freezing degree days (°C·day)
+    pub fdd: f64,
+    /// Days with a temperature below the frost threshold
+    pub frost_days: usize,
+    /// Thaws following a freeze
+    pub freeze_thaw_cycles: usize,
+}
+
+/// Count the thaws following a freeze in `temps`.
+fn freeze_thaw_cycles(temps: impl Iterator<Item = f64>, margin: f64) -> usize {
+    let mut frozen: Option<bool> = None;
+    let mut cycles = 0;
+    for t in temps {
+        if t < -margin {
+            frozen = Some(true);
+        } else if t > margin {
+            if frozen == Some(true) {
+                cycles += 1;
+            }
+            frozen = Some(false);
+        }
+    }
+    cycles
+}
+
+/// Growing and freezing degree days, frost days and freeze-thaw cycles of
+/// every period of `options.period`. Missing temperatures are skipped.
+/// Records are expected in time order.
+#[must_use]
+pub fn climate_indices(records: &[TmsRecord], options: &IndexOptions) -> Vec<ClimateIndices> {
+    let datetimes: Vec<NaiveDateTime> = records.iter().map(|r| r.datetime).collect();
+    let step = detect_step(&datetimes);
+    let days = step.map_or(0.0, step_days);
+
+    group_by_period(records, options.period, step)
+        .into_iter()
+        .map(|(start, end, members)| {
+            let temps: Vec<f64> = members
+                .iter()
+                .map(|r| options.sensor.value(r))
+                .filter(|t| !t.is_nan())
+                .collect();
+            let mut daily_min: BTreeMap<NaiveDate, f64> = BTreeMap::new();
+            for r in &members {
+                let t = options.sensor.value(r);
+                if !t.is_nan() {
+                    let min = daily_min.entry(r.datetime.date()).or_insert(t);
+                    *min = min.min(t);
+                }
+            }
+            ClimateIndices {
+                start,
+                end,
+                gdd: temps
+                    .iter()
+                    .map(|t| (t - options.gdd_base).max(0.0) * days)
+                    .sum(),
+                fdd: temps
+                    .iter()
+                    .map(|t| (options.fdd_base - t).max(0.0) * days)
+                    .sum(),
+                frost_days: daily_min
+                    .values()
+                    .filter(|t| **t < options.frost_threshold)
+                    .count(),
+                freeze_thaw_cycles: freeze_thaw_cycles(
+                    temps.iter().copied(),
+                    options.freeze_thaw_margin,
+                ),
+            }
+        })
+        .collect()
+}
@@ -16,8 +16,44 @@
  * GNU General Public License for more details.
  */
 
+pub mod aggregate;
+pub mod batch;
+pub mod calibration;
+pub mod calibration_fit;
+pub mod clean;
+pub mod deployment;
+pub mod drought;
+pub mod ensemble;
 pub mod gas_flux;
+pub mod hydraulics;
+pub mod indices;
+pub mod merge;
+pub mod qc;
+pub mod resample;
+pub mod sensor;
+pub mod snow;
+pub mod soil;
+pub mod thermal;
+pub mod tomst;
 pub mod vwc;
 
+pub use aggregate::*;
+pub use batch::*;
+pub use calibration::*;
+pub use calibration_fit::*;
+pub use clean::*;
+pub use deployment::*;
+pub use drought::*;
+pub use ensemble::*;
 pub use gas_flux::*;
+pub use hydraulics::*;
+pub use indices::*;
+pub use merge::*;
+pub use qc::*;
+pub use resample::*;
+pub use sensor::*;
+pub use snow::*;
+pub use soil::*;
+pub use thermal::*;
+pub use tomst::*;
 pub use vwc::*;
@@ -5,66 +5,276 @@
  * See lib.rs for full license attribution.
  */
 
-use csv::WriterBuilder;
-use soil_sensor_toolbox::{process_file, SoilType, SoilTypeModel};
+mod cli;
+
+use cli::{
+    csv_writer, format_value, print_clean_report, print_deployment, print_merge_report,
+    print_snow_summary, print_usage, process_args, process_batch_args, write_aggregated,
+    write_drought, write_ensemble, write_indices, write_thermal, CliArgs, Feature, RowFormat,
+};
+use csv::Writer;
+use soil_sensor_toolbox::{
+    clean, detect_deployment, drought_stats, find_tms_files, locality_file_stem,
+    logger_serial_from_path, mc_calc_snow, merge_tms_files, process_batch_with, process_ensemble,
+    process_readings, process_records, qc_flags, resample, snow_summary, soil_heat_flux,
+    trim_to_deployment, write_tms_file, CleanOptions, DetectOptions, MoistureSensor, QcFlags,
+    QcOptions, SnowOptions, ThermalOptions, TmsReadOptions, TmsReader, VwcReader, VwcRecord,
+};
 use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 use std::process;
 
-fn print_usage() {
-    println!("Usage: soil-sensor-toolbox <input_file> <soil_type>");
-    println!("\nAvailable soil types:");
-    for soil in &SoilType::ALL {
-        println!("  {}", soil.as_str());
+/// VWC of the readings of another sensor family than TMS.
+fn read_sensor(sensor: &dyn MoistureSensor, cli: &CliArgs) -> anyhow::Result<Vec<VwcRecord>> {
+    let readings = sensor.read(&mut open_input(&cli.input_file)?, &cli.options.read)?;
+    Ok(process_readings(&readings, sensor, &cli.options))
+}
+
+/// Input file, or standard input for `-`.
+fn open_input(path: &str) -> std::io::Result<Box<dyn Read>> {
+    if path == "-" {
+        Ok(Box::new(io::stdin().lock()))
+    } else {
+        Ok(Box::new(File::open(path)?))
     }
-    println!("\nExample:");
-    println!("  soil-sensor-toolbox data.csv universal");
-    println!("  soil-sensor-toolbox data.csv peat");
 }
 
-fn process_args(args: &[String]) -> Result<(String, SoilTypeModel), String> {
-    if args.len() != 3 {
-        return Err("Invalid number of arguments".to_string());
+fn run_single(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let cli = process_args(args)?;
+    let detect = cli.has(Feature::DetectDeployment)
+        && (cli.options.deployment.installed.is_none() || cli.options.deployment.removed.is_none());
+    // Cleaning, resampling, deployment detection, QC, snow, heat flux and
+    // the ensemble need the whole series, otherwise rows are streamed
+    let columns = cli.features.iter().any(|f| f.column());
+    let mut extra: Vec<(&'static str, Vec<String>)> = Vec::new();
+    let records: Box<dyn Iterator<Item = anyhow::Result<VwcRecord>>> =
+        if detect || cli.features.iter().any(|f| f.whole_series()) {
+            let mut tms = TmsReader::with_options(open_input(&cli.input_file)?, cli.options.read)
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            if cli.has(Feature::Clean) {
+                let (cleaned, report) = clean(&tms, &CleanOptions::default())?;
+                print_clean_report(&report);
+                tms = cleaned;
+            }
+            let mut options = cli.options.clone();
+            if detect {
+                options.deployment = options
+                    .deployment
+                    .or(detect_deployment(&tms, &DetectOptions::default()));
+                print_deployment(&options.deployment);
+            }
+            // Extra columns are aligned with the records of the deployment
+            if columns || cli.has(Feature::Indices) {
+                tms = trim_to_deployment(&tms, &options.deployment);
+            }
+            if cli.has(Feature::Resample) {
+                let (resampled, flags) = resample(&tms, &cli.resampling)?;
+                tms = resampled;
+                extra.push((
+                    "filled",
+                    flags.iter().map(|f| f.as_str().to_string()).collect(),
+                ));
+            }
+            if cli.has(Feature::Indices) {
+                write_indices(&tms, &cli.indices)?;
+            }
+            if cli.has(Feature::Qc) {
+                let flags = qc_flags(&tms, &QcOptions::default());
+                extra.push(("qc", flags.iter().map(QcFlags::codes).collect()));
+            }
+            if cli.has(Feature::Snow) {
+                let snow = mc_calc_snow(&tms, &SnowOptions::default());
+                print_snow_summary(&snow_summary(&snow));
+                let values = snow
+                    .iter()
+                    .map(|s| s.snow.map_or("NA".to_string(), |v| u8::from(v).to_string()));
+                extra.push(("snow", values.collect()));
+            }
+            if let Some(ensemble) = &cli.ensemble {
+                let records = process_ensemble(&tms, ensemble, &options);
+                write_ensemble(&records, ensemble, cli.ensemble_all, &options)?;
+            }
+            let vwc = process_records(&tms, cli.params, &options);
+            if cli.has(Feature::Thermal) {
+                let thermal = ThermalOptions::default();
+                write_thermal(&tms, &thermal)?;
+                let flux = soil_heat_flux(&tms, &vwc, &thermal);
+                extra.push((
+                    "heat_flux_W_m2",
+                    flux.iter().map(|f| format_value(f.flux)).collect(),
+                ));
+            }
+            Box::new(vwc.into_iter().map(Ok))
+        } else if let Some(sensor) = &cli.sensor {
+            Box::new(read_sensor(sensor.as_ref(), &cli)?.into_iter().map(Ok))
+        } else {
+            Box::new(VwcReader::new(
+                open_input(&cli.input_file)?,
+                cli.params,
+                cli.options.clone(),
+            ))
+        };
+    let format = RowFormat::new(
+        &cli.options,
+        extra.iter().map(|(name, _)| *name).collect(),
+        cli.hydraulics,
+    );
+    let mut kept = Vec::new();
+    let mut wtr = csv_writer("output.csv")?;
+    wtr.write_record(format.header(&[]))?;
+    for (i, rec) in records.enumerate() {
+        let rec = rec?;
+        let values: Vec<String> = extra.iter().map(|(_, column)| column[i].clone()).collect();
+        wtr.write_record(format.row(&[], &rec, &values))?;
+        if cli.has(Feature::Aggregate) || cli.has(Feature::Drought) {
+            kept.push(rec);
+        }
     }
+    wtr.flush()?;
+    println!("wrote output.csv");
+    if cli.has(Feature::Aggregate) {
+        write_aggregated(&kept, &cli.aggregate)?;
+    }
+    if cli.has(Feature::Drought) {
+        let drought = &cli.drought;
+        let path = PathBuf::from(format!("drought_{}.csv", drought.period.as_str()));
+        let stats = drought_stats(&kept, drought);
+        write_drought(&path, &[], &[(Vec::new(), stats)], drought)?;
+    }
+
+    Ok(())
+}
 
-    let input_file = args[1].clone();
-    let soil_type: SoilTypeModel = match args[2].as_str().try_into() {
-        Ok(soil) => soil,
-        Err(e) => {
-            eprintln!("Error: {e}");
-            println!();
-            print_usage();
+fn run_batch(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let batch = process_batch_args(args)?;
+    let files = find_tms_files(&batch.dir)?;
+    if files.is_empty() {
+        return Err(format!("No data_*.csv files found in {}", batch.dir).into());
+    }
+    let format = RowFormat::new(&batch.options.vwc, Vec::new(), batch.hydraulics);
+    std::fs::create_dir_all(&batch.out_dir)?;
 
-            process::exit(1);
-        }
+    // Files are processed grouped by output file, in file name order, and
+    // written as they come so that a single output is open at a time
+    let output = |path: &Path| {
+        let name = if batch.per_locality {
+            let serial = logger_serial_from_path(path).unwrap_or_default();
+            let locality = batch.metadata.locality(&serial);
+            format!("output_{}.csv", locality_file_stem(locality))
+        } else {
+            "output_batch.csv".to_string()
+        };
+        batch.out_dir.join(name)
     };
+    let mut files = files;
+    files.sort_by_key(|path| output(path));
 
-    Ok((input_file, soil_type))
+    let mut current: Option<(PathBuf, Writer<File>)> = None;
+    let mut drought = Vec::new();
+    let mut failed = 0;
+    process_batch_with(&files, &batch.metadata, &batch.options, |path, result| {
+        let series = match result {
+            Ok(series) => series,
+            Err(e) => {
+                eprintln!("Error: {}: {e:#}", path.display());
+                failed += 1;
+                return Ok(());
+            }
+        };
+        let out = output(path);
+        if current.as_ref().is_none_or(|(open, _)| *open != out) {
+            if let Some((open, mut wtr)) = current.take() {
+                wtr.flush()?;
+                println!("wrote {}", open.display());
+            }
+            let mut wtr = csv_writer(&out)?;
+            wtr.write_record(format.header(&["locality", "serial"]))?;
+            current = Some((out, wtr));
+        }
+        if let Some((_, wtr)) = current.as_mut() {
+            let prefix = [series.locality(), series.serial.as_str()];
+            for rec in &series.records {
+                wtr.write_record(format.row(&prefix, rec, &[]))?;
+            }
+        }
+        if let Some(options) = &batch.drought {
+            let prefix = vec![series.locality().to_string(), series.serial.clone()];
+            drought.push((prefix, drought_stats(&series.records, options)));
+        }
+        Ok(())
+    })?;
+    if let Some((open, mut wtr)) = current {
+        wtr.flush()?;
+        println!("wrote {}", open.display());
+    }
+    if let Some(options) = &batch.drought {
+        let path = batch
+            .out_dir
+            .join(format!("drought_{}.csv", options.period.as_str()));
+        write_drought(&path, &["locality", "serial"], &drought, options)?;
+    }
+    println!(
+        "processed {} of {} files",
+        files.len() - failed,
+        files.len()
+    );
+    if failed > 0 && !batch.keep_going {
+        return Err(format!("{failed} of {} files failed, see --keep-going", files.len()).into());
+    }
+
+    Ok(())
+}
+
+fn run_merge(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    let mut out_dir = PathBuf::from(".");
+    let mut rest = args[2..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--out" => {
+                out_dir = PathBuf::from(rest.next().ok_or("Missing value for --out")?);
+            }
+            flag if flag.starts_with("--") => return Err(format!("Unknown option: {flag}").into()),
+            path if Path::new(path).is_dir() => files.extend(find_tms_files(path)?),
+            path => files.push(PathBuf::from(path)),
+        }
+    }
+    if files.is_empty() {
+        return Err("No data_*.csv files to merge".into());
+    }
+
+    let loggers = merge_tms_files(&files, &TmsReadOptions::default())?;
+    std::fs::create_dir_all(&out_dir)?;
+    for logger in &loggers {
+        print_merge_report(logger);
+        let path = out_dir.join(format!("data_{}_merged.csv", logger.serial));
+        write_tms_file(&path, &logger.records)?;
+        println!("wrote {}", path.display());
+        // Downloads after a clock reset between downloads, as they were read
+        for (source, records) in &logger.report.set_aside {
+            let name = logger.files[*source].file_name().unwrap_or_default();
+            let path = out_dir.join(format!("reset_{}", name.to_string_lossy()));
+            write_tms_file(&path, records)?;
+            println!("wrote {}", path.display());
+        }
+    }
+
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() != 3 {
+    if args.len() < 3 {
         print_usage();
         process::exit(1);
     }
 
-    let (input_file, soil_type) = process_args(&args)?;
-    let records = process_file(input_file, soil_type.id)?;
-    let mut wtr = WriterBuilder::new()
-        .delimiter(b';')
-        .from_path("output.csv")?;
-    wtr.write_record(["datetime", "raw", "temp", "VWC_moisture"])?;
-    for (dt, raw, temp, vwc) in records {
-        wtr.write_record(&[
-            dt.format("%Y.%m.%d %H:%M").to_string(),
-            raw.to_string(),
-            temp.to_string(),
-            format!("{vwc:.6}"),
-        ])?;
+    match args[1].as_str() {
+        "batch" => run_batch(&args),
+        "merge" => run_merge(&args),
+        _ => run_single(&args),
     }
-    wtr.flush()?;
-    println!("wrote output.csv");
-
-    Ok(())
 }
@@ -0,0 +1,225 @@
+/*
+ * Merging of Logger Downloads
+ *
+ * Every download of a TOMST logger repeats its whole memory, so successive
+ * `data_<serial>_*.csv` files of one logger overlap. Merging combines them
+ * into one continuous series per logger serial, dropping the repeated
+ * records and reporting the readings that disagree between downloads and
+ * the resets of the logger clock.
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+use crate::tomst::{read_tms_file_with_options, TmsReadOptions, TmsRecord};
+use crate::vwc::logger_serial_from_path;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Two downloads holding different readings for the same moment.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MergeConflict {
+    /// Index of the download whose record was kept
+    pub kept_source: usize,
+    pub kept: TmsRecord,
+    /// Index of the download whose record was dropped
+    pub dropped_source: usize,
+    pub dropped: TmsRecord,
+}
+
+/// Logger clock set back within a download or between two downloads, as
+/// the datetimes of the two records around the reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClockReset {
+    /// Index of the download
+    pub source: usize,
+    /// Last datetime before the reset
+    pub before: NaiveDateTime,
+    /// First datetime after the reset
+    pub after: NaiveDateTime,
+    /// Records of the download kept apart from the merged series, 0 for a
+    /// reset within a download
+    pub set_aside: usize,
+}
+
+/// Summary of a merge.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MergeReport {
+    /// Number of merged downloads
+    pub sources: usize,
+    /// Records read from all downloads
+    pub count_read: usize,
+    /// Records dropped because an identical record was already merged
+    pub count_duplicates: usize,
+    pub conflicts: Vec<MergeConflict>,
+    pub clock_resets: Vec<ClockReset>,
+    /// Downloads kept apart after a clock reset between downloads, with
+    /// their index
+    pub set_aside: Vec<(usize, Vec<TmsRecord>)>,
+}
+
+impl MergeReport {
+    /// Whether the downloads agree and no clock reset was found.
+    #[must_use]
+    pub fn is_consistent(&self) -> bool {
+        self.conflicts.is_empty() && self.clock_resets.is_empty()
+    }
+}
+
+/// Same readings, ignoring the record index which restarts with every
+/// download. Missing values are equal.
+fn same_readings(a: &TmsRecord, b: &TmsRecord) -> bool {
+    let eq = |x: f64, y: f64| (x - y).abs() < f64::EPSILON || (x.is_nan() && y.is_nan());
+    eq(a.t1, b.t1)
+        && eq(a.t2, b.t2)
+        && eq(a.t3, b.t3)
+        && eq(a.moisture, b.moisture)
+        && a.shake == b.shake
+        && a.err_flag == b.err_flag
+}
+
+/// Clock reset between the downloads merged so far and `records`: most of
+/// its records at an already merged moment hold other readings, as when the
+/// logger memory was cleared and its clock set back.
+fn reset_between(
+    merged: &BTreeMap<DateTime<Utc>, (usize, TmsRecord)>,
+    source: usize,
+    records: &[TmsRecord],
+) -> Option<ClockReset> {
+    let (mut overlap, mut conflicting) = (0, 0);
+    for rec in records {
+        if let Some((_, kept)) = merged.get(&rec.utc_datetime()) {
+            overlap += 1;
+            if !same_readings(kept, rec) {
+                conflicting += 1;
+            }
+        }
+    }
+    if conflicting * 2 <= overlap {
+        return None;
+    }
+    let (_, (_, last)) = merged.last_key_value()?;
+    Some(ClockReset {
+        source,
+        before: last.datetime,
+        after: records.first()?.datetime,
+        set_aside: records.len(),
+    })
+}
+
+/// Merge the records of several downloads of one logger into a single
+/// series in time order.
+///
+/// Records are matched on their UTC datetime. For a moment present in
+/// several downloads the record of the first download in `downloads` is
+/// kept; a later record with other readings is reported as a conflict.
+/// A datetime earlier than the one of the previous record of the same
+/// download is reported as a clock reset; the records after it are merged
+/// at the datetimes the logger wrote. A download whose records mostly
+/// disagree with the ones already merged at the same datetimes is reported
+/// as a clock reset between downloads and kept apart in
+/// [`MergeReport::set_aside`], as its datetimes cannot be trusted.
+#[must_use]
+pub fn merge_records(downloads: &[Vec<TmsRecord>]) -> (Vec<TmsRecord>, MergeReport) {
+    let mut merged: BTreeMap<DateTime<Utc>, (usize, TmsRecord)> = BTreeMap::new();
+    let mut report = MergeReport {
+        sources: downloads.len(),
+        ..MergeReport::default()
+    };
+    for (source, records) in downloads.iter().enumerate() {
+        report.count_read += records.len();
+        if let Some(reset) = reset_between(&merged, source, records) {
+            report.clock_resets.push(reset);
+            report.set_aside.push((source, records.clone()));
+            continue;
+        }
+        for (i, rec) in records.iter().enumerate() {
+            if i > 0 && rec.utc_datetime() < records[i - 1].utc_datetime() {
+                report.clock_resets.push(ClockReset {
+                    source,
+                    before: records[i - 1].datetime,
+                    after: rec.datetime,
+                    set_aside: 0,
+                });
+            }
+            match merged.entry(rec.utc_datetime()) {
+                Entry::Vacant(e) => {
+                    e.insert((source, *rec));
+                }
+                Entry::Occupied(e) => {
+                    let (kept_source, kept) = *e.get();
+                    if same_readings(&kept, rec) {
+                        report.count_duplicates += 1;
+                    } else {
+                        report.conflicts.push(MergeConflict {
+                            kept_source,
+                            kept,
+                            dropped_source: source,
+                            dropped: *rec,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    let records = merged.into_values().map(|(_, rec)| rec).collect();
+    (records, report)
+}
+
+/// Downloads of one logger merged into one series.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergedLogger {
+    pub serial: String,
+    /// Merged files, indexed by the `source` of the report
+    pub files: Vec<PathBuf>,
+    pub records: Vec<TmsRecord>,
+    pub report: MergeReport,
+}
+
+/// Read `files`, group them by the logger serial of their name
+/// (`data_<serial>_*.csv`) and merge the downloads of each logger, in the
+/// order of `files`. Loggers are returned sorted by serial.
+///
+/// # Errors
+///
+/// This function returns an error if a file cannot be read (see
+/// [`crate::read_tms_file`]) or has no logger serial in its name.
+pub fn merge_tms_files<P: AsRef<Path>>(
+    files: &[P],
+    options: &TmsReadOptions,
+) -> Result<Vec<MergedLogger>> {
+    let mut loggers: BTreeMap<String, (Vec<PathBuf>, Vec<Vec<TmsRecord>>)> = BTreeMap::new();
+    for file in files {
+        let path = file.as_ref();
+        let serial = logger_serial_from_path(path)
+            .ok_or_else(|| anyhow!("Cannot derive logger serial from {}", path.display()))?;
+        let records = read_tms_file_with_options(path, options)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let (paths, downloads) = loggers.entry(serial).or_default();
+        paths.push(path.to_path_buf());
+        downloads.push(records);
+    }
+    Ok(loggers
+        .into_iter()
+        .map(|(serial, (files, downloads))| {
+            let (records, report) = merge_records(&downloads);
+            MergedLogger {
+                serial,
+                files,
+                records,
+                report,
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,147 @@
+/*
+ * Quality Control
+ *
+ * Per-record quality flags of TMS series: implausible raw moisture counts,
+ * air-like counts of a sensor out of the soil, sudden steps of the count and
+ * days whose soil temperature follows the air temperature (exposure).
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+use crate::deployment::AIR_MOISTURE_COUNT;
+use crate::tomst::TmsRecord;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Thresholds of [`qc_flags`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QcOptions {
+    /// Plausible range of the raw moisture count
+    pub raw_min: f64,
+    pub raw_max: f64,
+    /// Counts below this value are read in air
+    pub air_max: f64,
+    /// Largest plausible change of the count between consecutive records
+    pub max_step: f64,
+    /// Days where the T1 diurnal amplitude reaches this fraction of the T3
+    /// (air) amplitude are flagged as exposed
+    pub exposure_ratio: f64,
+    /// T3 diurnal amplitude (°C) below which exposure is not assessed
+    pub min_air_amplitude: f64,
+}
+
+impl Default for QcOptions {
+    fn default() -> Self {
+        Self {
+            raw_min: 50.0,
+            raw_max: 4000.0,
+            air_max: AIR_MOISTURE_COUNT,
+            max_step: 300.0,
+            exposure_ratio: 0.8,
+            min_air_amplitude: 3.0,
+        }
+    }
+}
+
+/// Quality flags of one record, all `false` for a plausible record.
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QcFlags {
+    /// Raw count outside the plausible range
+    pub out_of_range: bool,
+    /// Raw count at the level of air
+    pub air: bool,
+    /// Sudden change of the raw count since the previous record
+    pub step: bool,
+    /// T1 diurnal amplitude close to the air amplitude on this day
+    pub exposure: bool,
+}
+
+impl QcFlags {
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        *self == QcFlags::default()
+    }
+
+    /// Raised flags joined with `|` (`range`, `air`, `step`, `exposure`),
+    /// `ok` if none.
+    #[must_use]
+    pub fn codes(&self) -> String {
+        if self.is_ok() {
+            return "ok".to_string();
+        }
+        [
+            (self.out_of_range, "range"),
+            (self.air, "air"),
+            (self.step, "step"),
+            (self.exposure, "exposure"),
+        ]
+        .iter()
+        .filter(|(raised, _)| *raised)
+        .map(|(_, code)| *code)
+        .collect::<Vec<_>>()
+        .join("|")
+    }
+}
+
+/// Diurnal amplitude (max - min) of `value` per day.
+fn daily_amplitude(records: &[TmsRecord], value: fn(&TmsRecord) -> f64) -> HashMap<NaiveDate, f64> {
+    let mut range: HashMap<NaiveDate, (f64, f64)> = HashMap::new();
+    for rec in records {
+        let v = value(rec);
+        if v.is_nan() {
+            continue;
+        }
+        let e = range
+            .entry(rec.datetime.date())
+            .or_insert((f64::INFINITY, f64::NEG_INFINITY));
+        *e = (e.0.min(v), e.1.max(v));
+    }
+    range
+        .into_iter()
+        .map(|(d, (lo, hi))| (d, hi - lo))
+        .collect()
+}
+
+/// Quality flags of every record of a TMS series, in time order.
+///
+/// Missing values raise no flag; a step is only assessed between two
+/// consecutive non-missing counts.
+#[must_use]
+pub fn qc_flags(records: &[TmsRecord], options: &QcOptions) -> Vec<QcFlags> {
+    let amp_t1 = daily_amplitude(records, |r| r.t1);
+    let amp_t3 = daily_amplitude(records, |r| r.t3);
+    let exposed = |date: NaiveDate| match (amp_t1.get(&date), amp_t3.get(&date)) {
+        (Some(t1), Some(t3)) => {
+            *t3 >= options.min_air_amplitude && *t1 >= options.exposure_ratio * t3
+        }
+        _ => false,
+    };
+
+    let mut prev = f64::NAN;
+    records
+        .iter()
+        .map(|rec| {
+            let raw = rec.moisture;
+            let flags = QcFlags {
+                out_of_range: raw < options.raw_min || raw > options.raw_max,
+                air: raw < options.air_max,
+                step: (raw - prev).abs() > options.max_step,
+                exposure: exposed(rec.datetime.date()),
+            };
+            if !raw.is_nan() {
+                prev = raw;
+            }
+            flags
+        })
+        .collect()
+}
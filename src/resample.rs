@@ -0,0 +1,238 @@
+/*
+ * Resampling
+ *
+ * Re-gridding of logger time series to a regular step, interpolating the
+ * records that fall between the original ones and across short gaps, with
+ * a flag telling original, interpolated and missing records apart.
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+#![allow(clippy::cast_precision_loss)]
+
+use crate::clean::TimeSeriesRecord;
+use crate::tomst::TmsRecord;
+use crate::vwc::VwcRecord;
+use anyhow::{bail, Result};
+use chrono::{NaiveDateTime, NaiveTime, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+/// A record whose measurements can be interpolated.
+pub trait Interpolate: TimeSeriesRecord {
+    /// Record at `datetime`, a fraction `t` (0 to 1) of the way from `self`
+    /// to `next`. Missing values stay missing; non-numeric fields are taken
+    /// from the nearest record.
+    #[must_use]
+    fn lerp(&self, next: &Self, t: f64, datetime: NaiveDateTime) -> Self;
+
+    /// Copy of the nearest of `self` and `next` (`self` on ties) moved to
+    /// `datetime`.
+    #[must_use]
+    fn nearest(&self, next: &Self, t: f64, datetime: NaiveDateTime) -> Self {
+        let mut nearest = if t <= 0.5 { self } else { next }.clone();
+        nearest.set_datetime(datetime);
+        nearest
+    }
+}
+
+fn lerp_value(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+impl Interpolate for TmsRecord {
+    fn lerp(&self, next: &Self, t: f64, datetime: NaiveDateTime) -> Self {
+        let nearest = if t <= 0.5 { self } else { next };
+        Self {
+            datetime,
+            t1: lerp_value(self.t1, next.t1, t),
+            t2: lerp_value(self.t2, next.t2, t),
+            t3: lerp_value(self.t3, next.t3, t),
+            moisture: lerp_value(self.moisture, next.moisture, t),
+            index: None,
+            ..*nearest
+        }
+    }
+
+    fn nearest(&self, next: &Self, t: f64, datetime: NaiveDateTime) -> Self {
+        Self {
+            datetime,
+            index: None,
+            ..*if t <= 0.5 { self } else { next }
+        }
+    }
+}
+
+impl Interpolate for VwcRecord {
+    fn lerp(&self, next: &Self, t: f64, datetime: NaiveDateTime) -> Self {
+        let nearest = if t <= 0.5 { self } else { next };
+        Self {
+            datetime,
+            raw: lerp_value(self.raw, next.raw, t),
+            temp: lerp_value(self.temp, next.temp, t),
+            vwc: lerp_value(self.vwc, next.vwc, t),
+            ..*nearest
+        }
+    }
+}
+
+/// How records between two original records are computed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Interpolation {
+    /// Linear interpolation between the neighbouring records
+    #[default]
+    Linear,
+    /// Nearest neighbouring record, the earlier one on ties
+    Nearest,
+    /// Missing values
+    None,
+}
+
+impl Interpolation {
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Interpolation::Linear => "linear",
+            Interpolation::Nearest => "nearest",
+            Interpolation::None => "none",
+        }
+    }
+}
+
+impl TryFrom<&str> for Interpolation {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s.to_lowercase().as_str() {
+            "linear" => Ok(Interpolation::Linear),
+            "nearest" => Ok(Interpolation::Nearest),
+            "none" => Ok(Interpolation::None),
+            _ => Err(format!("Unknown interpolation: {s}")),
+        }
+    }
+}
+
+/// Origin of a resampled record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResampleFlag {
+    /// Original record on the grid
+    Original,
+    /// Interpolated from the neighbouring records
+    Interpolated,
+    /// In a gap longer than the maximum, or not interpolated
+    Missing,
+}
+
+impl ResampleFlag {
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResampleFlag::Original => "original",
+            ResampleFlag::Interpolated => "interpolated",
+            ResampleFlag::Missing => "missing",
+        }
+    }
+}
+
+/// Options of [`resample`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ResampleOptions {
+    /// Step of the regular grid, aligned to midnight
+    pub step: TimeDelta,
+    pub interpolation: Interpolation,
+    /// Longest time between two original records that is interpolated
+    pub max_gap: TimeDelta,
+}
+
+impl Default for ResampleOptions {
+    fn default() -> Self {
+        Self {
+            step: TimeDelta::minutes(15),
+            interpolation: Interpolation::Linear,
+            max_gap: TimeDelta::hours(1),
+        }
+    }
+}
+
+/// First multiple of `step` since midnight at or after `datetime`.
+fn ceil_to_step(datetime: NaiveDateTime, step: TimeDelta) -> NaiveDateTime {
+    let midnight = datetime.date().and_time(NaiveTime::MIN);
+    let secs = (datetime - midnight).num_seconds();
+    let step_secs = step.num_seconds();
+    midnight + TimeDelta::seconds((secs + step_secs - 1).div_euclid(step_secs) * step_secs)
+}
+
+/// Re-grid `records` to `options.step`, from the first grid point at or
+/// after the first record to the last record.
+///
+/// Original records on the grid are kept. The other grid points are
+/// interpolated from the original records before and after them when these
+/// are at most `max_gap` apart, and missing otherwise. Original records off
+/// the grid are only used for the interpolation. Returns the records with
+/// the flag of each.
+///
+/// # Errors
+///
+/// Returns an error if the step is not a positive whole number of seconds
+/// or the records are not in time order.
+pub fn resample<T: Interpolate>(
+    records: &[T],
+    options: &ResampleOptions,
+) -> Result<(Vec<T>, Vec<ResampleFlag>)> {
+    let step = options.step;
+    if step.num_seconds() <= 0 || step.subsec_nanos() != 0 {
+        bail!("Invalid resampling step: {step}");
+    }
+    if records
+        .windows(2)
+        .any(|w| w[1].datetime() <= w[0].datetime())
+    {
+        bail!("Records must be in strictly increasing time order, see clean");
+    }
+    let (Some(first), Some(last)) = (records.first(), records.last()) else {
+        return Ok((Vec::new(), Vec::new()));
+    };
+
+    let mut out = Vec::new();
+    let mut flags = Vec::new();
+    // Index of the first original record at or after the grid point
+    let mut next = 0;
+    let mut datetime = ceil_to_step(first.datetime(), step);
+    while datetime <= last.datetime() {
+        while records[next].datetime() < datetime {
+            next += 1;
+        }
+        let after = &records[next];
+        if after.datetime() == datetime {
+            out.push(after.clone());
+            flags.push(ResampleFlag::Original);
+        } else {
+            // The first grid point is at or after the first record
+            let before = &records[next - 1];
+            let gap = after.datetime() - before.datetime();
+            let t = (datetime - before.datetime()).num_seconds() as f64 / gap.num_seconds() as f64;
+            let filled = match options.interpolation {
+                _ if gap > options.max_gap => None,
+                Interpolation::Linear => Some(before.lerp(after, t, datetime)),
+                Interpolation::Nearest => Some(before.nearest(after, t, datetime)),
+                Interpolation::None => None,
+            };
+            if let Some(rec) = filled {
+                out.push(rec);
+                flags.push(ResampleFlag::Interpolated);
+            } else {
+                out.push(before.missing_at(datetime));
+                flags.push(ResampleFlag::Missing);
+            }
+        }
+        datetime += step;
+    }
+    Ok((out, flags))
+}
@@ -0,0 +1,586 @@
+/*
+ * Moisture Sensors
+ *
+ * Abstraction over soil moisture sensor families: how their raw data files
+ * are read and how a raw reading is converted to VWC. TMS loggers implement
+ * it in vwc.rs; METER TEROS 10/11, Decagon 5TM and Truebner SMT100 probes
+ * are defined here, reading ZENTRA Utility or ZENTRA Cloud exports for the
+ * METER and Decagon probes and SMT100 logger exports.
+ *
+ * Calibration equations:
+ * - TEROS 10 and TEROS 11: METER Group user manuals, mineral soil and
+ *   soilless media calibrations
+ * - 5TM and SMT100: permittivity output of the probe, converted with the
+ *   equations below
+ * - Mineral soils: Topp, Davis & Annan (1980), 10.1029/WR016i003p00574
+ * - Organic soils: Roth, Malicki & Plagge (1992),
+ *   10.1111/j.1365-2389.1992.tb00115.x
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+use crate::calibration::{parse_table_datetime, Calibration};
+use crate::tomst::TmsReadOptions;
+use crate::vwc::{FrozenPolicy, VwcOptions, VwcRecord, FROZEN_T};
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use csv::{ReaderBuilder, StringRecord};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// One raw reading of a moisture sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SensorReading {
+    pub datetime: NaiveDateTime,
+    /// Time zone code of `datetime` (offset in 15 minute units), 0 when the
+    /// file does not give it
+    pub timezone: i32,
+    /// Raw reading, in the unit of the sensor family
+    pub raw: f64,
+    /// Temperature (°C) of the VWC conversion, `NaN` when not measured
+    pub temp: f64,
+    /// Soil temperature (°C) deciding whether the soil is frozen, T1 for
+    /// TMS loggers and `temp` otherwise
+    pub soil_temp: f64,
+}
+
+/// A family of soil moisture sensors.
+pub trait MoistureSensor {
+    /// Short name of the family, e.g. `teros11`.
+    fn name(&self) -> &'static str;
+
+    /// VWC (m³/m³, clamped between 0 and 1) of a raw reading at `temp` (°C,
+    /// `NaN` when unknown), with a field `calibration` of the raw readings.
+    /// Only TMS counts have a calibration, other families ignore it.
+    fn vwc(&self, raw: f64, temp: f64, calibration: Calibration) -> f64;
+
+    /// Read the raw readings of a data file in the native export layout of
+    /// the family, see [`read_sensor_csv`] for the default layout. The
+    /// datetime, time zone and decimal settings of TOMST files in `_options`
+    /// are ignored by the default reader.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data cannot be read or parsed.
+    fn read(&self, reader: &mut dyn Read, _options: &TmsReadOptions) -> Result<Vec<SensorReading>> {
+        read_sensor_csv(reader)
+    }
+}
+
+/// Substrate of the calibration equation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Substrate {
+    /// Mineral soil
+    #[default]
+    Mineral,
+    /// Organic soil, peat or soilless media
+    Organic,
+}
+
+impl Substrate {
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Substrate::Mineral => "mineral",
+            Substrate::Organic => "organic",
+        }
+    }
+}
+
+impl TryFrom<&str> for Substrate {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s.to_lowercase().as_str() {
+            "mineral" => Ok(Substrate::Mineral),
+            "organic" | "soilless" => Ok(Substrate::Organic),
+            _ => Err(format!("Unknown substrate: {s}")),
+        }
+    }
+}
+
+/// VWC of a mineral soil from its relative permittivity (Topp et al. 1980).
+#[must_use]
+pub fn topp(permittivity: f64) -> f64 {
+    let e = permittivity;
+    -5.3e-2 + 2.92e-2 * e - 5.5e-4 * e * e + 4.3e-6 * e * e * e
+}
+
+/// VWC of an organic soil from its relative permittivity (Roth et al. 1992).
+#[must_use]
+pub fn roth_organic(permittivity: f64) -> f64 {
+    let e = permittivity;
+    -2.33e-2 + 2.85e-2 * e - 4.31e-4 * e * e + 3.04e-6 * e * e * e
+}
+
+fn permittivity_vwc(permittivity: f64, substrate: Substrate) -> f64 {
+    match substrate {
+        Substrate::Mineral => topp(permittivity),
+        Substrate::Organic => roth_organic(permittivity),
+    }
+}
+
+/// Polynomial with the coefficients of `x³`, `x²`, `x` and 1.
+fn cubic(x: f64, coeffs: (f64, f64, f64, f64)) -> f64 {
+    ((coeffs.0 * x + coeffs.1) * x + coeffs.2) * x + coeffs.3
+}
+
+/// METER TEROS 10, raw reading in mV.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Teros10 {
+    pub substrate: Substrate,
+    /// Logger port of the probe in ZENTRA exports, the first port with a mV
+    /// column when not given
+    pub port: Option<u8>,
+}
+
+impl MoistureSensor for Teros10 {
+    fn name(&self) -> &'static str {
+        "teros10"
+    }
+
+    fn vwc(&self, raw: f64, _temp: f64, _calibration: Calibration) -> f64 {
+        let coeffs = match self.substrate {
+            Substrate::Mineral => (4.824e-10, -2.278e-6, 3.898e-3, -2.154),
+            Substrate::Organic => (5.439e-10, -2.731e-6, 4.868e-3, -2.683),
+        };
+        cubic(raw, coeffs).clamp(0.0, 1.0)
+    }
+
+    fn read(&self, reader: &mut dyn Read, _options: &TmsReadOptions) -> Result<Vec<SensorReading>> {
+        read_zentra_csv(reader, self, &["mv"], self.port)
+    }
+}
+
+/// METER TEROS 11 (and TEROS 12), raw reading in counts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Teros11 {
+    pub substrate: Substrate,
+    /// Logger port of the probe in ZENTRA exports, the first port with a raw
+    /// VWC column when not given
+    pub port: Option<u8>,
+}
+
+impl MoistureSensor for Teros11 {
+    fn name(&self) -> &'static str {
+        "teros11"
+    }
+
+    fn vwc(&self, raw: f64, _temp: f64, _calibration: Calibration) -> f64 {
+        let coeffs = match self.substrate {
+            Substrate::Mineral => (0.0, 0.0, 3.879e-4, -0.6956),
+            Substrate::Organic => (6.771e-10, -5.105e-6, 1.302e-2, -10.848),
+        };
+        cubic(raw, coeffs).clamp(0.0, 1.0)
+    }
+
+    fn read(&self, reader: &mut dyn Read, _options: &TmsReadOptions) -> Result<Vec<SensorReading>> {
+        read_zentra_csv(reader, self, &["raw vwc", "counts"], self.port)
+    }
+}
+
+/// Decagon 5TM, raw reading as relative permittivity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Decagon5tm {
+    pub substrate: Substrate,
+    /// Logger port of the probe in ZENTRA exports, the first port with a
+    /// permittivity column when not given
+    pub port: Option<u8>,
+}
+
+impl MoistureSensor for Decagon5tm {
+    fn name(&self) -> &'static str {
+        "5tm"
+    }
+
+    fn vwc(&self, raw: f64, _temp: f64, _calibration: Calibration) -> f64 {
+        permittivity_vwc(raw, self.substrate).clamp(0.0, 1.0)
+    }
+
+    fn read(&self, reader: &mut dyn Read, _options: &TmsReadOptions) -> Result<Vec<SensorReading>> {
+        read_zentra_csv(reader, self, &["permittivity", "dielectric"], self.port)
+    }
+}
+
+/// Truebner SMT100, raw reading as relative permittivity (SDI-12 and Modbus
+/// output). Raw oscillator counts are not supported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Smt100 {
+    pub substrate: Substrate,
+    /// Probe number in SMT100 logger exports, the first probe when not given
+    pub port: Option<u8>,
+}
+
+impl MoistureSensor for Smt100 {
+    fn name(&self) -> &'static str {
+        "smt100"
+    }
+
+    fn vwc(&self, raw: f64, _temp: f64, _calibration: Calibration) -> f64 {
+        permittivity_vwc(raw, self.substrate).clamp(0.0, 1.0)
+    }
+
+    fn read(&self, reader: &mut dyn Read, _options: &TmsReadOptions) -> Result<Vec<SensorReading>> {
+        read_smt100_csv(reader, self.port)
+    }
+}
+
+/// Supported sensor families.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SensorFamily {
+    /// TOMST TMS loggers
+    #[default]
+    Tms,
+    Teros10,
+    Teros11,
+    Decagon5tm,
+    Smt100,
+}
+
+impl SensorFamily {
+    pub const ALL: [SensorFamily; 5] = [
+        SensorFamily::Tms,
+        SensorFamily::Teros10,
+        SensorFamily::Teros11,
+        SensorFamily::Decagon5tm,
+        SensorFamily::Smt100,
+    ];
+
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SensorFamily::Tms => "tms",
+            SensorFamily::Teros10 => "teros10",
+            SensorFamily::Teros11 => "teros11",
+            SensorFamily::Decagon5tm => "5tm",
+            SensorFamily::Smt100 => "smt100",
+        }
+    }
+
+    /// Sensor of the family with the calibration for `substrate`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for TMS loggers, which are calibrated per soil type
+    /// with a [`crate::VwcParams`] rather than per substrate.
+    pub fn sensor(self, substrate: Substrate) -> Result<Box<dyn MoistureSensor>, String> {
+        self.sensor_on_port(substrate, None)
+    }
+
+    /// Sensor of the family with the calibration for `substrate`, reading the
+    /// probe on logger `port` (or SMT100 probe number) of multi-probe exports.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for TMS loggers, see [`SensorFamily::sensor`].
+    pub fn sensor_on_port(
+        self,
+        substrate: Substrate,
+        port: Option<u8>,
+    ) -> Result<Box<dyn MoistureSensor>, String> {
+        match self {
+            SensorFamily::Tms => Err("TMS loggers take a soil type, not a substrate".to_string()),
+            SensorFamily::Teros10 => Ok(Box::new(Teros10 { substrate, port })),
+            SensorFamily::Teros11 => Ok(Box::new(Teros11 { substrate, port })),
+            SensorFamily::Decagon5tm => Ok(Box::new(Decagon5tm { substrate, port })),
+            SensorFamily::Smt100 => Ok(Box::new(Smt100 { substrate, port })),
+        }
+    }
+}
+
+impl TryFrom<&str> for SensorFamily {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s.to_lowercase().as_str() {
+            "tms" | "tms4" => Ok(SensorFamily::Tms),
+            "teros10" => Ok(SensorFamily::Teros10),
+            "teros11" | "teros12" => Ok(SensorFamily::Teros11),
+            "5tm" => Ok(SensorFamily::Decagon5tm),
+            "smt100" => Ok(SensorFamily::Smt100),
+            _ => Err(format!("Unknown sensor family: {s}")),
+        }
+    }
+}
+
+/// Datetime of a sensor data row: the formats of
+/// [`parse_table_datetime`], `%m/%d/%Y %I:%M %p` as exported by ZENTRA
+/// Utility, or `%d.%m.%Y %H:%M:%S` and `%d.%m.%Y %H:%M` as exported by SMT100
+/// loggers.
+fn parse_sensor_datetime(s: &str) -> Option<NaiveDateTime> {
+    let s = s.trim();
+    parse_table_datetime(s).ok().or_else(|| {
+        ["%m/%d/%Y %I:%M %p", "%d.%m.%Y %H:%M:%S", "%d.%m.%Y %H:%M"]
+            .into_iter()
+            .find_map(|fmt| NaiveDateTime::parse_from_str(s, fmt).ok())
+    })
+}
+
+fn parse_sensor_value(field: Option<&str>) -> Result<f64> {
+    match field.map(str::trim) {
+        None | Some("" | "NA" | "#N/A" | "NaN") => Ok(f64::NAN),
+        Some(v) => v
+            .parse()
+            .map_err(|e| anyhow!("Invalid sensor value {v}: {e}")),
+    }
+}
+
+/// Number at the end of a header, e.g. the port of `Port2` or the probe of
+/// `Permittivity 2`.
+fn trailing_number(s: &str) -> Option<u8> {
+    let s = s.trim();
+    let digits = s.len() - s.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    s[s.len() - digits..].parse().ok()
+}
+
+/// Rows of a delimited export and whether its values have decimal commas.
+fn read_rows<R: Read>(mut reader: R) -> Result<(Vec<StringRecord>, bool)> {
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+    // The most frequent separator of the first lines, as exports start with
+    // free text metadata
+    let head: Vec<&str> = content.lines().take(20).collect();
+    let delimiter = [b';', b'\t', b',']
+        .into_iter()
+        .max_by_key(|d| {
+            head.iter()
+                .map(|line| line.matches(char::from(*d)).count())
+                .sum::<usize>()
+        })
+        .unwrap_or(b',');
+
+    let rows = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content.as_bytes())
+        .records()
+        .collect::<csv::Result<_>>()?;
+    Ok((rows, delimiter != b','))
+}
+
+/// Columns of the readings of one probe in an export.
+struct ProbeColumns {
+    /// Datetime, or date when `time` is set
+    datetime: usize,
+    time: Option<usize>,
+    raw: usize,
+    temp: Option<usize>,
+}
+
+impl ProbeColumns {
+    /// Layout of [`read_sensor_csv`].
+    const GENERIC: ProbeColumns = ProbeColumns {
+        datetime: 0,
+        time: None,
+        raw: 1,
+        temp: Some(2),
+    };
+
+    /// Readings of `rows`, skipping the rows without a datetime.
+    fn readings(&self, rows: &[StringRecord], decimal_comma: bool) -> Result<Vec<SensorReading>> {
+        let mut out = Vec::new();
+        for row in rows {
+            let mut datetime = row.get(self.datetime).unwrap_or_default().to_string();
+            if let Some(time) = self.time {
+                datetime = format!("{datetime} {}", row.get(time).unwrap_or_default());
+            }
+            let Some(datetime) = parse_sensor_datetime(&datetime) else {
+                continue;
+            };
+            let value = |column: Option<usize>| {
+                let field = column.and_then(|i| row.get(i));
+                if decimal_comma {
+                    parse_sensor_value(field.map(|v| v.replace(',', ".")).as_deref())
+                } else {
+                    parse_sensor_value(field)
+                }
+            };
+            let temp = value(self.temp)?;
+            out.push(SensorReading {
+                datetime,
+                timezone: 0,
+                raw: value(Some(self.raw))?,
+                temp,
+                soil_temp: temp,
+            });
+        }
+        Ok(out)
+    }
+}
+
+/// Column of the first header containing one of `keywords` (lowercase) on
+/// `port`, or on any port when not given. Logger battery and temperature
+/// columns are never chosen.
+fn find_column(
+    headers: &[(Option<u8>, String)],
+    keywords: &[&str],
+    port: Option<u8>,
+) -> Option<usize> {
+    headers.iter().position(|(p, name)| {
+        port.is_none_or(|port| *p == Some(port))
+            && keywords.iter().any(|k| name.contains(k))
+            && !name.contains("battery")
+            && !name.contains("logger")
+    })
+}
+
+/// Read a ZENTRA Utility or ZENTRA Cloud export of a METER logger: a row of
+/// ports (`Port1`, `Port2`, ...), a row of sensor names and a row of
+/// measurements (e.g. `mV Raw`, `°C Soil Temperature`) above the data. The
+/// raw reading of `sensor` is the first measurement containing one of `raw`
+/// on `port`, or on any port when not given, and the temperature the soil
+/// temperature of the same port. Exports without a port row, e.g. reduced to
+/// one probe, are read as [`read_sensor_csv`] does.
+fn read_zentra_csv<R: Read>(
+    reader: R,
+    sensor: &dyn MoistureSensor,
+    raw: &[&str],
+    port: Option<u8>,
+) -> Result<Vec<SensorReading>> {
+    let (rows, decimal_comma) = read_rows(reader)?;
+    let is_port =
+        |s: &str| s.trim().to_lowercase().starts_with("port") && trailing_number(s).is_some();
+    let Some(ports) = rows
+        .iter()
+        .take(20)
+        .position(|row| row.iter().skip(1).any(is_port))
+    else {
+        return ProbeColumns::GENERIC.readings(&rows, decimal_comma);
+    };
+    // The measurements are in the last header row
+    let data = rows[ports + 1..]
+        .iter()
+        .position(|row| row.get(0).and_then(parse_sensor_datetime).is_some())
+        .map_or(rows.len(), |i| ports + 1 + i);
+    let headers: Vec<(Option<u8>, String)> = rows[data - 1]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let port = rows[ports].get(i).and_then(trailing_number);
+            (port, name.to_lowercase())
+        })
+        .collect();
+
+    let raw = find_column(&headers, raw, port).ok_or_else(|| {
+        let on_port = port.map_or(String::new(), |p| format!(" on port {p}"));
+        anyhow!("No {} reading{on_port} in the ZENTRA export", sensor.name())
+    })?;
+    let columns = ProbeColumns {
+        datetime: 0,
+        time: None,
+        raw,
+        temp: find_column(&headers, &["soil temperature"], headers[raw].0),
+    };
+    columns.readings(&rows[data..], decimal_comma)
+}
+
+/// Read an SMT100 logger export: a header row with `Date` and `Time` (or a
+/// single datetime column) and `Permittivity <n>` and `Temperature <n>` of
+/// each probe `n`, usually `;` separated with decimal commas. The probe is
+/// `port`, or the first one when not given; columns without a number belong
+/// to probe 1. Exports without a permittivity header are read as
+/// [`read_sensor_csv`] does.
+fn read_smt100_csv<R: Read>(reader: R, port: Option<u8>) -> Result<Vec<SensorReading>> {
+    let (rows, decimal_comma) = read_rows(reader)?;
+    let Some(header) = rows.iter().take(20).position(|row| {
+        row.iter()
+            .any(|s| s.to_lowercase().contains("permittivity"))
+    }) else {
+        return ProbeColumns::GENERIC.readings(&rows, decimal_comma);
+    };
+    let headers: Vec<(Option<u8>, String)> = rows[header]
+        .iter()
+        .map(|s| (trailing_number(s).or(Some(1)), s.trim().to_lowercase()))
+        .collect();
+
+    let raw = find_column(&headers, &["permittivity"], port).ok_or_else(|| {
+        anyhow!(
+            "No permittivity of probe {} in the SMT100 export",
+            port.unwrap_or(1)
+        )
+    })?;
+    let columns = ProbeColumns {
+        datetime: headers
+            .iter()
+            .position(|(_, name)| name.contains("date") || name.contains("timestamp"))
+            .unwrap_or(0),
+        time: headers.iter().position(|(_, name)| name == "time"),
+        raw,
+        temp: find_column(&headers, &["temp"], headers[raw].0),
+    };
+    columns.readings(&rows[header + 1..], decimal_comma)
+}
+
+/// Read a delimited sensor export with the datetime in the first column,
+/// the raw reading in the second and the soil temperature, if any, in the
+/// third, e.g. a ZENTRA or SMT100 logger export reduced to one probe.
+///
+/// The delimiter (`,`, `;` or tab) is detected from the first lines, values
+/// of `;` and tab separated files may have decimal commas, header and
+/// metadata rows without a datetime in the first column are skipped, and
+/// empty, `NA` and `#N/A` values are missing.
+///
+/// # Errors
+///
+/// Returns an error if the data cannot be read or a value is not a number.
+pub fn read_sensor_csv<R: Read>(reader: R) -> Result<Vec<SensorReading>> {
+    let (rows, decimal_comma) = read_rows(reader)?;
+    ProbeColumns::GENERIC.readings(&rows, decimal_comma)
+}
+
+/// VWC records of the readings of `sensor`, dropping the readings outside
+/// the deployment of `options` and applying its calibration schedule and
+/// frozen soil policy, as [`crate::process_records`] does for TMS records.
+#[must_use]
+pub fn process_readings(
+    readings: &[SensorReading],
+    sensor: &dyn MoistureSensor,
+    options: &VwcOptions,
+) -> Vec<VwcRecord> {
+    readings
+        .iter()
+        .filter(|r| options.deployment.contains(r.datetime))
+        .map(|r| {
+            let frozen = r.soil_temp < FROZEN_T;
+            let vwc = sensor.vwc(r.raw, r.temp, options.calibration.at(r.datetime));
+            VwcRecord {
+                datetime: r.datetime,
+                timezone: r.timezone,
+                raw: r.raw,
+                temp: r.temp,
+                vwc: if frozen && options.frozen == FrozenPolicy::Missing {
+                    f64::NAN
+                } else {
+                    vwc
+                },
+                frozen,
+            }
+        })
+        .collect()
+}
+
+/// Read the data file of `sensor` at `path` with the read options of
+/// `options` and compute its VWC, see [`process_readings`].
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or read.
+pub fn process_sensor_file<P: AsRef<Path>>(
+    path: P,
+    sensor: &dyn MoistureSensor,
+    options: &VwcOptions,
+) -> Result<Vec<VwcRecord>> {
+    let readings = sensor.read(&mut File::open(path)?, &options.read)?;
+    Ok(process_readings(&readings, sensor, options))
+}
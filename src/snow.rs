@@ -0,0 +1,196 @@
+/*
+ * Snow Cover Detection
+ *
+ * Snow cover estimated from the damped diurnal temperature range of the
+ * surface (T2) or air (T3) sensor, following myClim `mc_calc_snow` and
+ * `mc_calc_snow_agg`.
+ * Original myClim package: https://github.com/ibot-geoecology/myClim
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+#![allow(clippy::cast_precision_loss)]
+
+use crate::clean::detect_step;
+use crate::tomst::TmsRecord;
+use crate::vwc::TempSensor;
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Parameters of [`mc_calc_snow`] (defaults of myClim `mc_calc_snow`).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SnowOptions {
+    /// Sensor covered by the snow (default: T2)
+    pub sensor: TempSensor,
+    /// Maximal temperature range (°C) within the window under snow
+    pub range: f64,
+    /// Maximal temperature (°C) within the window under snow
+    pub tmax: f64,
+    /// Length of the window
+    pub window: TimeDelta,
+}
+
+impl Default for SnowOptions {
+    fn default() -> Self {
+        Self {
+            sensor: TempSensor::T2,
+            range: 1.0,
+            tmax: 0.5,
+            window: TimeDelta::days(3),
+        }
+    }
+}
+
+/// Snow cover at one datetime, `None` when it cannot be assessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnowRecord {
+    pub datetime: NaiveDateTime,
+    pub snow: Option<bool>,
+}
+
+/// Minimum and maximum of `values[j..j + w]` for every `j`, `None` when the
+/// window holds a missing value.
+fn window_extremes(values: &[f64], w: usize) -> Vec<Option<(f64, f64)>> {
+    if w == 0 || values.len() < w {
+        return Vec::new();
+    }
+    let (mut min, mut max): (VecDeque<usize>, VecDeque<usize>) = (VecDeque::new(), VecDeque::new());
+    let mut last_nan: Option<usize> = None;
+    let mut out = Vec::with_capacity(values.len() - w + 1);
+    for (i, &v) in values.iter().enumerate() {
+        if v.is_nan() {
+            last_nan = Some(i);
+        } else {
+            while min.back().is_some_and(|&j| values[j] >= v) {
+                min.pop_back();
+            }
+            min.push_back(i);
+            while max.back().is_some_and(|&j| values[j] <= v) {
+                max.pop_back();
+            }
+            max.push_back(i);
+        }
+        if i + 1 < w {
+            continue;
+        }
+        let start = i + 1 - w;
+        while min.front().is_some_and(|&j| j < start) {
+            min.pop_front();
+        }
+        while max.front().is_some_and(|&j| j < start) {
+            max.pop_front();
+        }
+        out.push(match (last_nan, min.front(), max.front()) {
+            (Some(n), _, _) if n >= start => None,
+            (_, Some(&lo), Some(&hi)) => Some((values[lo], values[hi])),
+            _ => None,
+        });
+    }
+    out
+}
+
+/// Detect snow cover from the temperature of `options.sensor` (myClim
+/// `mc_calc_snow`).
+///
+/// A record is under snow when the temperature stays below `tmax` with a
+/// range below `range` during the `window` ending or starting at it. Records
+/// whose windows both hold missing values or leave the series are `None`.
+/// Records are expected in time order with a regular step.
+#[must_use]
+pub fn mc_calc_snow(records: &[TmsRecord], options: &SnowOptions) -> Vec<SnowRecord> {
+    let datetimes: Vec<NaiveDateTime> = records.iter().map(|r| r.datetime).collect();
+    let w = detect_step(&datetimes).map_or(0, |step| {
+        usize::try_from(options.window.num_seconds() / step.num_seconds().max(1)).unwrap_or(0)
+    });
+    let values: Vec<f64> = records.iter().map(|r| options.sensor.value(r)).collect();
+    let extremes = window_extremes(&values, w.max(1));
+    let snowy = |j: usize| {
+        extremes
+            .get(j)
+            .copied()
+            .flatten()
+            .map(|(lo, hi)| hi < options.tmax && hi - lo < options.range)
+    };
+
+    records
+        .iter()
+        .enumerate()
+        .map(|(i, rec)| {
+            // Window ending at the record, and starting at it
+            let before = (i + 1).checked_sub(w.max(1)).and_then(snowy);
+            let after = snowy(i);
+            let snow = match (before, after) {
+                (None, None) => None,
+                (b, a) => Some(b.unwrap_or(false) || a.unwrap_or(false)),
+            };
+            SnowRecord {
+                datetime: rec.datetime,
+                snow,
+            }
+        })
+        .collect()
+}
+
+/// Snow cover statistics of a series (myClim `mc_calc_snow_agg`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SnowSummary {
+    /// Duration of the snow cover in days
+    pub snow_days: f64,
+    /// Date of the first record under snow
+    pub first_day: Option<NaiveDate>,
+    /// Date of the last record under snow
+    pub last_day: Option<NaiveDate>,
+    /// Duration of the longest continuous snow cover in days
+    pub longest_period: f64,
+    /// First and last datetime of the longest continuous snow cover
+    pub longest_start: Option<NaiveDateTime>,
+    pub longest_end: Option<NaiveDateTime>,
+}
+
+/// Summarise the output of [`mc_calc_snow`]. Records that could not be
+/// assessed interrupt a continuous snow cover.
+#[must_use]
+pub fn snow_summary(snow: &[SnowRecord]) -> SnowSummary {
+    let datetimes: Vec<NaiveDateTime> = snow.iter().map(|s| s.datetime).collect();
+    let Some(step) = detect_step(&datetimes) else {
+        return SnowSummary::default();
+    };
+    let step_days = step.num_seconds() as f64 / 86_400.0;
+
+    let mut summary = SnowSummary::default();
+    let mut count = 0usize;
+    let mut run: Option<(usize, usize)> = None;
+    let mut longest: Option<(usize, usize)> = None;
+    for (i, s) in snow.iter().enumerate() {
+        if s.snow == Some(true) {
+            count += 1;
+            summary.first_day.get_or_insert(s.datetime.date());
+            summary.last_day = Some(s.datetime.date());
+            let (start, _) = run.unwrap_or((i, i));
+            run = Some((start, i));
+        } else {
+            run = None;
+        }
+        if let Some((start, end)) = run {
+            if longest.is_none_or(|(s, e)| end - start > e - s) {
+                longest = Some((start, end));
+            }
+        }
+    }
+    summary.snow_days = count as f64 * step_days;
+    if let Some((start, end)) = longest {
+        summary.longest_period = (end - start + 1) as f64 * step_days;
+        summary.longest_start = Some(snow[start].datetime);
+        summary.longest_end = Some(snow[end].datetime);
+    }
+    summary
+}
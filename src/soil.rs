@@ -0,0 +1,213 @@
+/*
+ * Soil Parameter Registry
+ *
+ * Calibration curves (a, b, c) and temperature correction constants used by
+ * the VWC calculation, extensible at runtime with site-specific soils loaded
+ * from CSV or TOML tables.
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+use crate::vwc::{SoilType, ACOR_T, REF_T, WCOR_T};
+use anyhow::{anyhow, bail, Context, Result};
+use csv::ReaderBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+
+/// Parameters of a soil calibration curve, VWC = a·count² + b·count + c,
+/// with the myClim temperature correction constants.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SoilParams {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    /// Reference temperature of the temperature correction (°C)
+    pub ref_t: f64,
+    pub acor_t: f64,
+    pub wcor_t: f64,
+}
+
+impl SoilParams {
+    /// Curve `(a, b, c)` with the default myClim temperature correction.
+    #[must_use]
+    pub fn new(a: f64, b: f64, c: f64) -> Self {
+        Self {
+            a,
+            b,
+            c,
+            ref_t: REF_T,
+            acor_t: ACOR_T,
+            wcor_t: WCOR_T,
+        }
+    }
+
+    /// Uncorrected VWC of a raw count.
+    #[must_use]
+    pub fn curve(&self, count: f64) -> f64 {
+        self.a * count * count + self.b * count + self.c
+    }
+}
+
+impl From<SoilType> for SoilParams {
+    fn from(soil: SoilType) -> Self {
+        let (a, b, c) = soil.coeffs();
+        Self::new(a, b, c)
+    }
+}
+
+/// One soil of a CSV row or TOML table.
+#[derive(Debug, Deserialize)]
+struct SoilEntry {
+    /// Name column of CSV tables; TOML tables are named by their key
+    #[serde(default, alias = "soil", alias = "soil_type")]
+    name: Option<String>,
+    a: f64,
+    b: f64,
+    c: f64,
+    ref_t: Option<f64>,
+    acor_t: Option<f64>,
+    wcor_t: Option<f64>,
+}
+
+impl SoilEntry {
+    fn params(&self) -> SoilParams {
+        SoilParams {
+            ref_t: self.ref_t.unwrap_or(REF_T),
+            acor_t: self.acor_t.unwrap_or(ACOR_T),
+            wcor_t: self.wcor_t.unwrap_or(WCOR_T),
+            ..SoilParams::new(self.a, self.b, self.c)
+        }
+    }
+}
+
+/// Soil curves available by name. Names are case-insensitive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SoilRegistry {
+    soils: BTreeMap<String, SoilParams>,
+}
+
+impl Default for SoilRegistry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl SoilRegistry {
+    /// Registry with the myClim soil types of [`SoilType::ALL`].
+    #[must_use]
+    pub fn builtin() -> Self {
+        Self {
+            soils: SoilType::ALL
+                .iter()
+                .map(|s| (s.as_str().to_string(), SoilParams::from(*s)))
+                .collect(),
+        }
+    }
+
+    /// Add or replace the soil `name`.
+    pub fn insert(&mut self, name: &str, params: SoilParams) {
+        self.soils.insert(name.to_lowercase(), params);
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<SoilParams> {
+        self.soils.get(&name.to_lowercase()).copied()
+    }
+
+    /// Registered soil names, in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.soils.keys().map(String::as_str)
+    }
+
+    /// Parameters of all registered soils, e.g. to override the temperature
+    /// correction constants at once.
+    pub fn params_mut(&mut self) -> impl Iterator<Item = &mut SoilParams> {
+        self.soils.values_mut()
+    }
+
+    /// Add the soils of a `.csv` or `.toml` file, see [`Self::extend_from_csv`]
+    /// and [`Self::extend_from_toml`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed, or has another
+    /// extension.
+    pub fn extend_from_path<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => {
+                self.extend_from_csv(std::fs::File::open(path)?)
+            }
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => {
+                self.extend_from_toml(&std::fs::read_to_string(path)?)
+            }
+            _ => bail!(
+                "Soil table must be a .csv or .toml file: {}",
+                path.display()
+            ),
+        }
+    }
+
+    /// Add the soils of a comma separated table with columns `name`, `a`,
+    /// `b`, `c` and optionally `ref_t`, `acor_t`, `wcor_t`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a row cannot be parsed.
+    pub fn extend_from_csv<R: Read>(&mut self, reader: R) -> Result<()> {
+        let mut rdr = ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        for result in rdr.deserialize() {
+            let entry: SoilEntry = result?;
+            let name = entry
+                .name
+                .as_deref()
+                .context("Soil table without a name column")?;
+            self.insert(name, entry.params());
+        }
+        Ok(())
+    }
+
+    /// Add the soils of a TOML document with one table per soil:
+    ///
+    /// ```toml
+    /// [alpine_loam]
+    /// a = -4.2e-8
+    /// b = 0.00035
+    /// c = -0.25
+    /// ref_t = 20.0  # optional, as acor_t and wcor_t
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the document cannot be parsed.
+    pub fn extend_from_toml(&mut self, content: &str) -> Result<()> {
+        let entries: BTreeMap<String, SoilEntry> =
+            toml::from_str(content).context("Invalid soil table")?;
+        for (name, entry) in entries {
+            self.insert(&name, entry.params());
+        }
+        Ok(())
+    }
+
+    /// Parameters of `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the soil if it is not registered.
+    pub fn resolve(&self, name: &str) -> Result<SoilParams> {
+        self.get(name)
+            .ok_or_else(|| anyhow!("Unknown soil type: {name}"))
+    }
+}
@@ -0,0 +1,216 @@
+/*
+ * Soil Thermal Properties
+ *
+ * Apparent thermal diffusivity of the top soil from the damping and the
+ * phase lag of the diurnal temperature cycle between the surface (T2) and
+ * soil (T1) sensors, and soil heat flux from the temperature gradient with
+ * the heat capacity of de Vries (1963) at the measured VWC.
+ *
+ * - Horton, Wierenga & Nielsen (1983), 10.2136/sssaj1983.03615995004700010005x
+ * - de Vries (1963), Thermal properties of soils, in Physics of Plant
+ *   Environment, North-Holland
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+#![allow(clippy::cast_precision_loss)]
+
+use crate::clean::detect_step;
+use crate::tomst::TmsRecord;
+use crate::vwc::VwcRecord;
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::f64::consts::PI;
+
+/// Angular frequency of the diurnal cycle (rad/s).
+pub const DIURNAL_OMEGA: f64 = 2.0 * PI / 86_400.0;
+/// Volumetric heat capacity of water (J/m³/K).
+pub const WATER_HEAT_CAPACITY: f64 = 4.18e6;
+/// Volumetric heat capacity of soil minerals (J/m³/K), de Vries (1963).
+pub const MINERAL_HEAT_CAPACITY: f64 = 1.92e6;
+/// Volumetric heat capacity of soil organic matter (J/m³/K), de Vries (1963).
+pub const ORGANIC_HEAT_CAPACITY: f64 = 2.51e6;
+
+/// Parameters of [`thermal_diffusivity`] and [`soil_heat_flux`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ThermalOptions {
+    /// Distance between the surface (T2) and soil (T1) sensors (m). T2 at
+    /// +2 cm is taken as the surface temperature, T1 is at -6 cm.
+    pub depth: f64,
+    /// Volume fraction of the mineral solids
+    pub mineral_fraction: f64,
+    /// Volume fraction of the organic matter
+    pub organic_fraction: f64,
+    /// Diffusivity (m²/s) of the heat flux, instead of the daily
+    /// amplitude estimate
+    pub diffusivity: Option<f64>,
+}
+
+impl Default for ThermalOptions {
+    fn default() -> Self {
+        Self {
+            depth: 0.06,
+            mineral_fraction: 0.5,
+            organic_fraction: 0.0,
+            diffusivity: None,
+        }
+    }
+}
+
+/// Volumetric heat capacity (J/m³/K) of a soil at `vwc` (de Vries 1963).
+#[must_use]
+pub fn volumetric_heat_capacity(vwc: f64, options: &ThermalOptions) -> f64 {
+    MINERAL_HEAT_CAPACITY * options.mineral_fraction
+        + ORGANIC_HEAT_CAPACITY * options.organic_fraction
+        + WATER_HEAT_CAPACITY * vwc
+}
+
+/// Apparent thermal diffusivity of one day.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ThermalDiffusivity {
+    pub date: NaiveDate,
+    /// Amplitude of the diurnal cycle at the surface, T2 (°C)
+    pub amplitude_surface: f64,
+    /// Amplitude of the diurnal cycle in the soil, T1 (°C)
+    pub amplitude_soil: f64,
+    /// Delay of the soil cycle behind the surface one (hours), within
+    /// ±12 hours
+    pub phase_lag: f64,
+    /// Diffusivity from the amplitude damping (m²/s), `NaN` without damping
+    pub amplitude: f64,
+    /// Diffusivity from the phase lag (m²/s), `NaN` without lag
+    pub phase: f64,
+}
+
+/// Amplitude and phase (rad) of the first diurnal harmonic of `(seconds
+/// since midnight, temperature)` samples evenly covering a day.
+fn diurnal_harmonic(samples: &[(f64, f64)]) -> (f64, f64) {
+    let n = samples.len() as f64;
+    let (a, b) = samples.iter().fold((0.0, 0.0), |(a, b), (t, v)| {
+        let x = DIURNAL_OMEGA * t;
+        (a + v * x.cos(), b + v * x.sin())
+    });
+    let (a, b) = (2.0 * a / n, 2.0 * b / n);
+    (a.hypot(b), b.atan2(a))
+}
+
+/// Diffusivity of every complete day of `records` from the first diurnal
+/// harmonic of T2 and T1 (Horton et al. 1983):
+/// `D = ω z² / (2 ln(A2/A1)²)` from the damping and `D = ω z² / (2 Δφ²)`
+/// from the phase lag, `z` being [`ThermalOptions::depth`].
+///
+/// Days with a missing temperature or record are skipped. Records are
+/// expected in time order with a regular step.
+#[must_use]
+pub fn thermal_diffusivity(
+    records: &[TmsRecord],
+    options: &ThermalOptions,
+) -> Vec<ThermalDiffusivity> {
+    let datetimes: Vec<NaiveDateTime> = records.iter().map(|r| r.datetime).collect();
+    let Some(step) = detect_step(&datetimes) else {
+        return Vec::new();
+    };
+    let per_day = usize::try_from(86_400 / step.num_seconds().max(1)).unwrap_or(0);
+
+    let mut days: BTreeMap<NaiveDate, Vec<&TmsRecord>> = BTreeMap::new();
+    for r in records {
+        days.entry(r.datetime.date()).or_default().push(r);
+    }
+    let z2 = options.depth * options.depth;
+    days.into_iter()
+        .filter(|(_, members)| {
+            members.len() == per_day && members.iter().all(|r| !r.t1.is_nan() && !r.t2.is_nan())
+        })
+        .map(|(date, members)| {
+            let seconds = |r: &TmsRecord| (r.datetime.time() - NaiveTime::MIN).num_seconds() as f64;
+            let surface: Vec<(f64, f64)> = members.iter().map(|r| (seconds(r), r.t2)).collect();
+            let soil: Vec<(f64, f64)> = members.iter().map(|r| (seconds(r), r.t1)).collect();
+            let (amplitude_surface, phase_surface) = diurnal_harmonic(&surface);
+            let (amplitude_soil, phase_soil) = diurnal_harmonic(&soil);
+            // Within half a day either way, negative when the soil leads
+            let lag = (phase_soil - phase_surface + PI).rem_euclid(2.0 * PI) - PI;
+            let damping = (amplitude_surface / amplitude_soil).ln();
+            ThermalDiffusivity {
+                date,
+                amplitude_surface,
+                amplitude_soil,
+                phase_lag: lag / DIURNAL_OMEGA / 3600.0,
+                amplitude: if damping > 0.0 {
+                    DIURNAL_OMEGA * z2 / (2.0 * damping * damping)
+                } else {
+                    f64::NAN
+                },
+                phase: if lag > 0.0 {
+                    DIURNAL_OMEGA * z2 / (2.0 * lag * lag)
+                } else {
+                    f64::NAN
+                },
+            }
+        })
+        .collect()
+}
+
+/// Soil heat flux at one datetime.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HeatFluxRecord {
+    pub datetime: NaiveDateTime,
+    /// Volumetric heat capacity (J/m³/K)
+    pub heat_capacity: f64,
+    /// Thermal conductivity (W/m/K), diffusivity times heat capacity
+    pub conductivity: f64,
+    /// Heat flux between the surface and T1 (W/m²), positive into the soil
+    pub flux: f64,
+}
+
+/// Soil heat flux of every record, `λ (T2 - T1) / z` with the conductivity
+/// `λ` from the diffusivity of the day (amplitude estimate of
+/// [`thermal_diffusivity`], or [`ThermalOptions::diffusivity`]) and the heat
+/// capacity at the VWC of the record in `vwc` (output of
+/// [`crate::process_records`]), matched by datetime.
+///
+/// Records without a VWC or a diffusivity get a `NaN` flux.
+#[must_use]
+pub fn soil_heat_flux(
+    records: &[TmsRecord],
+    vwc: &[VwcRecord],
+    options: &ThermalOptions,
+) -> Vec<HeatFluxRecord> {
+    let daily: HashMap<NaiveDate, f64> = if options.diffusivity.is_some() {
+        HashMap::new()
+    } else {
+        thermal_diffusivity(records, options)
+            .into_iter()
+            .map(|d| (d.date, d.amplitude))
+            .collect()
+    };
+    let vwc: HashMap<NaiveDateTime, f64> = vwc.iter().map(|r| (r.datetime, r.vwc)).collect();
+
+    records
+        .iter()
+        .map(|r| {
+            let diffusivity = options
+                .diffusivity
+                .or_else(|| daily.get(&r.datetime.date()).copied())
+                .unwrap_or(f64::NAN);
+            let heat_capacity = vwc
+                .get(&r.datetime)
+                .map_or(f64::NAN, |theta| volumetric_heat_capacity(*theta, options));
+            let conductivity = diffusivity * heat_capacity;
+            HeatFluxRecord {
+                datetime: r.datetime,
+                heat_capacity,
+                conductivity,
+                flux: conductivity * (r.t2 - r.t1) / options.depth,
+            }
+        })
+        .collect()
+}
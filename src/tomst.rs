@@ -0,0 +1,591 @@
+/*
+ * TOMST Data File Reader
+ *
+ * Reader for the `data_*.csv` files downloaded from TOMST loggers with the
+ * Lolly software, following the TOMST format definition of the myClim R
+ * package.
+ * Original myClim package: https://github.com/ibot-geoecology/myClim
+ *
+ * This program is free software; you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; either version 2 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU General Public License for more details.
+ */
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Offset, TimeDelta, Utc};
+use csv::{ReaderBuilder, StringRecord, StringRecordsIntoIter};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+
+/// Value written by TOMST loggers for a missing measurement.
+pub const TOMST_NA: f64 = -200.0;
+
+/// Length of one unit of the TOMST time zone column (seconds).
+const TZ_UNIT_SECS: i32 = 15 * 60;
+
+/// Datetime layouts written by the TOMST Lolly software.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TomstDateFormat {
+    /// `2023.05.30 06:45`
+    YmdHm,
+    /// `2023.05.30 06:45:00`
+    YmdHms,
+    /// `30.05.2023 06:45`
+    DmyHm,
+    /// `30.05.2023 06:45:00`
+    DmyHms,
+}
+
+impl TomstDateFormat {
+    pub const ALL: [TomstDateFormat; 4] = [
+        TomstDateFormat::YmdHm,
+        TomstDateFormat::YmdHms,
+        TomstDateFormat::DmyHm,
+        TomstDateFormat::DmyHms,
+    ];
+
+    /// `chrono` format string of the layout.
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TomstDateFormat::YmdHm => "%Y.%m.%d %H:%M",
+            TomstDateFormat::YmdHms => "%Y.%m.%d %H:%M:%S",
+            TomstDateFormat::DmyHm => "%d.%m.%Y %H:%M",
+            TomstDateFormat::DmyHms => "%d.%m.%Y %H:%M:%S",
+        }
+    }
+
+    /// First layout able to parse `s`.
+    #[must_use]
+    pub fn detect(s: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|f| NaiveDateTime::parse_from_str(s.trim(), f.as_str()).is_ok())
+    }
+}
+
+impl TryFrom<&str> for TomstDateFormat {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.as_str() == s)
+            .ok_or_else(|| {
+                let known: Vec<&str> = Self::ALL.iter().map(TomstDateFormat::as_str).collect();
+                format!(
+                    "Unknown TOMST datetime format: {s} (expected one of {})",
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+/// Decimal separator of the temperature columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DecimalSeparator {
+    /// `22.25`
+    Dot,
+    /// `22,25`, used by European Lolly installations
+    Comma,
+}
+
+impl TryFrom<&str> for DecimalSeparator {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s.to_lowercase().as_str() {
+            "dot" | "." => Ok(DecimalSeparator::Dot),
+            "comma" | "," => Ok(DecimalSeparator::Comma),
+            _ => Err(format!("Unknown decimal separator: {s}")),
+        }
+    }
+}
+
+/// Options for reading TOMST files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TmsReadOptions {
+    /// UTC offset of the logger clock, overriding the time zone column; a
+    /// multiple of 15 minutes, see [`parse_utc_offset`]
+    pub tz_offset: Option<FixedOffset>,
+    /// Convert datetimes to this UTC offset (a multiple of 15 minutes), keep
+    /// them as recorded if `None`
+    pub output_tz: Option<FixedOffset>,
+    /// Datetime layout, detected from the first record if `None`
+    pub date_format: Option<TomstDateFormat>,
+    /// Decimal separator, detected from the first fractional value if `None`
+    pub decimal: Option<DecimalSeparator>,
+}
+
+/// Parse a UTC offset such as `UTC`, `Z`, `+01:00`, `-0230` or `+2`. The
+/// offset must be a multiple of 15 minutes, the unit of TOMST time zone
+/// codes.
+///
+/// # Errors
+///
+/// Returns an error if `s` is not a valid offset.
+pub fn parse_utc_offset(s: &str) -> Result<FixedOffset, String> {
+    let err = || format!("Invalid UTC offset: {s}");
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("utc") || s == "Z" {
+        return Ok(Utc.fix());
+    }
+    let (sign, rest) = match s.as_bytes().first() {
+        Some(b'+') => (1, &s[1..]),
+        Some(b'-') => (-1, &s[1..]),
+        _ => return Err(err()),
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((h, m)) => (h, m),
+        None if rest.len() == 4 => rest.split_at(2),
+        None => (rest, "0"),
+    };
+    let hours: i32 = hours.parse().map_err(|_| err())?;
+    let minutes: i32 = minutes.parse().map_err(|_| err())?;
+    if minutes >= 60 {
+        return Err(err());
+    }
+    if minutes % 15 != 0 {
+        return Err(format!("UTC offset is not a multiple of 15 minutes: {s}"));
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).ok_or_else(err)
+}
+
+/// UTC offset of a TOMST time zone code.
+pub(crate) fn tz_code_offset(code: i32) -> FixedOffset {
+    FixedOffset::east_opt(code * TZ_UNIT_SECS).unwrap_or_else(|| Utc.fix())
+}
+
+/// One row of a TOMST TMS `data_*.csv` file:
+/// `index;datetime;timezone;T1;T2;T3;moisture;shake;errFlag;`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TmsRecord {
+    /// Record index as written by the logger, `None` for records inserted
+    /// by [`clean`](crate::clean::clean) or
+    /// [`resample`](crate::resample::resample)
+    pub index: Option<u64>,
+    pub datetime: NaiveDateTime,
+    /// Time zone code of the logger (offset in 15 minute units)
+    pub timezone: i32,
+    /// Soil temperature at -6 cm (°C), `NaN` when missing
+    pub t1: f64,
+    /// Surface temperature at +2 cm (°C), `NaN` when missing
+    pub t2: f64,
+    /// Air temperature at +15 cm (°C), `NaN` when missing
+    pub t3: f64,
+    /// Raw moisture count, `NaN` when missing
+    pub moisture: f64,
+    /// Shake sensor value
+    pub shake: i32,
+    /// Logger error flag (0 = no error)
+    pub err_flag: i32,
+}
+
+fn parse_value(field: &str, decimal: DecimalSeparator) -> Result<f64> {
+    let field = field.trim();
+    let v: f64 = match decimal {
+        DecimalSeparator::Dot => field.parse(),
+        DecimalSeparator::Comma => field.replace(',', ".").parse(),
+    }
+    .with_context(|| format!("Invalid number: {field}"))?;
+    Ok(if (v - TOMST_NA).abs() < f64::EPSILON {
+        f64::NAN
+    } else {
+        v
+    })
+}
+
+fn parse_int<T: std::str::FromStr>(field: &str) -> Result<T> {
+    field
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("Invalid integer: {field}"))
+}
+
+impl TmsRecord {
+    /// UTC offset of `datetime`, from the time zone column.
+    #[must_use]
+    pub fn utc_offset(&self) -> FixedOffset {
+        tz_code_offset(self.timezone)
+    }
+
+    /// `datetime` converted to UTC.
+    #[must_use]
+    pub fn utc_datetime(&self) -> DateTime<Utc> {
+        (self.datetime - TimeDelta::seconds(i64::from(self.utc_offset().local_minus_utc())))
+            .and_utc()
+    }
+
+    /// Apply `options`: replace the recorded offset with `tz_offset` and
+    /// convert `datetime` to `output_tz`. `timezone` always describes the
+    /// offset of the resulting `datetime`.
+    fn apply_time_zone(&mut self, options: &TmsReadOptions) {
+        if let Some(offset) = options.tz_offset {
+            self.timezone = offset.local_minus_utc() / TZ_UNIT_SECS;
+        }
+        if let Some(output) = options.output_tz {
+            self.datetime = self.utc_datetime().with_timezone(&output).naive_local();
+            self.timezone = output.local_minus_utc() / TZ_UNIT_SECS;
+        }
+    }
+
+    fn from_string_record(
+        rec: &StringRecord,
+        date_format: TomstDateFormat,
+        decimal: DecimalSeparator,
+    ) -> Result<Self> {
+        Ok(Self {
+            index: Some(parse_int(&rec[0])?),
+            datetime: NaiveDateTime::parse_from_str(rec[1].trim(), date_format.as_str())
+                .with_context(|| {
+                    format!("Invalid datetime for {}: {}", date_format.as_str(), &rec[1])
+                })?,
+            timezone: parse_int(&rec[2])?,
+            t1: parse_value(&rec[3], decimal)?,
+            t2: parse_value(&rec[4], decimal)?,
+            t3: parse_value(&rec[5], decimal)?,
+            moisture: parse_value(&rec[6], decimal)?,
+            shake: parse_int(&rec[7])?,
+            err_flag: parse_int(&rec[8])?,
+        })
+    }
+}
+
+/// Iterator over the records of a TOMST TMS file read from any source.
+///
+/// Thermologger and dendrometer files share the same layout and are read as
+/// [`TmsRecord`]s too, see [`read_tomst_file`] for typed records.
+///
+/// Datetime layout and decimal separator are detected from the data unless
+/// set in [`TmsReadOptions`].
+pub struct TmsReader<R: Read> {
+    records: StringRecordsIntoIter<R>,
+    options: TmsReadOptions,
+}
+
+impl<R: Read> TmsReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, TmsReadOptions::default())
+    }
+
+    pub fn with_options(reader: R, options: TmsReadOptions) -> Self {
+        let records = ReaderBuilder::new()
+            .delimiter(b';')
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(reader)
+            .into_records();
+        Self { records, options }
+    }
+}
+
+impl TmsReader<File> {
+    /// Open the TOMST file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> Iterator for TmsReader<R> {
+    type Item = Result<TmsRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rec = self.records.next()?;
+        Some(rec.map_err(Into::into).and_then(|rec| {
+            let line = rec.position().map_or(0, csv::Position::line);
+            let mut tms = self
+                .parse_record(&rec)
+                .with_context(|| format!("Line {line}"))?;
+            tms.apply_time_zone(&self.options);
+            Ok(tms)
+        }))
+    }
+}
+
+impl<R: Read> TmsReader<R> {
+    /// Detect the file format on the first records, then parse `rec`.
+    fn parse_record(&mut self, rec: &StringRecord) -> Result<TmsRecord> {
+        if rec.len() < 9 {
+            return Err(anyhow!(
+                "Expected 9 columns in TOMST record, found {}",
+                rec.len()
+            ));
+        }
+        if self.options.date_format.is_none() {
+            let f = TomstDateFormat::detect(&rec[1])
+                .ok_or_else(|| anyhow!("Unknown TOMST datetime format: {}", &rec[1]))?;
+            self.options.date_format = Some(f);
+        }
+        let date_format = self.options.date_format.unwrap_or(TomstDateFormat::YmdHm);
+        if self.options.decimal.is_none() {
+            let values = || (3..=6).map(|i| &rec[i]);
+            if values().any(|v| v.contains(',')) {
+                self.options.decimal = Some(DecimalSeparator::Comma);
+            } else if values().any(|v| v.contains('.')) {
+                self.options.decimal = Some(DecimalSeparator::Dot);
+            }
+        }
+        let decimal = self.options.decimal.unwrap_or(DecimalSeparator::Dot);
+        TmsRecord::from_string_record(rec, date_format, decimal)
+    }
+
+    /// Datetime layout and decimal separator in use, once detected.
+    #[must_use]
+    pub fn format(&self) -> (Option<TomstDateFormat>, Option<DecimalSeparator>) {
+        (self.options.date_format, self.options.decimal)
+    }
+}
+
+/// Read all records of the TOMST TMS file at `path`.
+///
+/// # Errors
+///
+/// This function returns an error if:
+/// - The file at `path` cannot be opened or read
+/// - A row has fewer than 9 columns
+/// - `DateTime` parsing fails (see [`TomstDateFormat`] for accepted layouts)
+/// - A numeric field cannot be parsed
+pub fn read_tms_file<P: AsRef<Path>>(path: P) -> Result<Vec<TmsRecord>> {
+    read_tms_file_with_options(path, &TmsReadOptions::default())
+}
+
+/// Same as [`read_tms_file`], applying the format and time zone settings of
+/// `options`.
+///
+/// # Errors
+///
+/// See [`read_tms_file`].
+pub fn read_tms_file_with_options<P: AsRef<Path>>(
+    path: P,
+    options: &TmsReadOptions,
+) -> Result<Vec<TmsRecord>> {
+    TmsReader::with_options(File::open(path)?, *options).collect()
+}
+
+fn format_value(v: f64) -> String {
+    if v.is_nan() {
+        TOMST_NA.to_string()
+    } else {
+        v.to_string()
+    }
+}
+
+/// Write `records` in the TOMST TMS layout (`YmdHm` datetimes, dot decimal
+/// separator, CRLF line endings), numbering the rows from 0.
+///
+/// # Errors
+///
+/// This function returns an error if writing to `writer` fails.
+pub fn write_tms<W: Write>(mut writer: W, records: &[TmsRecord]) -> Result<()> {
+    for (i, rec) in records.iter().enumerate() {
+        write!(
+            writer,
+            "{i};{};{};{};{};{};{};{};{};\r\n",
+            rec.datetime.format(TomstDateFormat::YmdHm.as_str()),
+            rec.timezone,
+            format_value(rec.t1),
+            format_value(rec.t2),
+            format_value(rec.t3),
+            format_value(rec.moisture),
+            rec.shake,
+            rec.err_flag
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write `records` to a TOMST TMS file at `path`, see [`write_tms`].
+///
+/// # Errors
+///
+/// This function returns an error if the file cannot be created or written.
+pub fn write_tms_file<P: AsRef<Path>>(path: P, records: &[TmsRecord]) -> Result<()> {
+    write_tms(BufWriter::new(File::create(path)?), records)
+}
+
+// ---------------------------------------------------------------------------
+// Thermologger and dendrometer files
+// ---------------------------------------------------------------------------
+
+/// Largest moisture count of a TMS logger (12-bit).
+const TMS_COUNT_MAX: f64 = 4095.0;
+
+/// Raw dendrometer value at 0 µm.
+const DENDRO_RAW_ZERO: f64 = 1279.0;
+/// Raw dendrometer value at the end of the measuring range.
+const DENDRO_RAW_MAX: f64 = 34000.0;
+/// Measuring range of the TOMST point dendrometer (µm).
+const DENDRO_RANGE_UM: f64 = 8890.0;
+
+/// Convert a raw TOMST point dendrometer value to a stem radius change in µm.
+#[must_use]
+pub fn dendro_raw_to_um(raw: f64) -> f64 {
+    (raw - DENDRO_RAW_ZERO) * DENDRO_RANGE_UM / (DENDRO_RAW_MAX - DENDRO_RAW_ZERO)
+}
+
+/// Kind of TOMST logger a `data_*.csv` file comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TomstLoggerKind {
+    /// TMS-4 soil moisture and temperature logger
+    Tms,
+    /// Thermologger (single temperature sensor)
+    Thermo,
+    /// Point dendrometer with temperature sensor
+    Dendro,
+}
+
+impl TomstLoggerKind {
+    /// Detect the logger kind from the moisture column: empty on
+    /// Thermologgers, above the 12-bit range of TMS counts on dendrometers.
+    /// Missing T2 and T3 alone do not make a file a Thermologger or
+    /// dendrometer file, as they are also missing on a TMS logger with
+    /// damaged sensors.
+    #[must_use]
+    pub fn detect(records: &[TmsRecord]) -> Self {
+        if records.is_empty() || records.iter().any(|r| !r.t2.is_nan() || !r.t3.is_nan()) {
+            return TomstLoggerKind::Tms;
+        }
+        if records
+            .iter()
+            .all(|r| r.moisture.is_nan() || r.moisture == 0.0)
+        {
+            TomstLoggerKind::Thermo
+        } else if records.iter().any(|r| r.moisture > TMS_COUNT_MAX) {
+            TomstLoggerKind::Dendro
+        } else {
+            TomstLoggerKind::Tms
+        }
+    }
+}
+
+/// One row of a TOMST Thermologger file.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ThermoRecord {
+    /// Record index as written by the logger, `None` for inserted records
+    pub index: Option<u64>,
+    pub datetime: NaiveDateTime,
+    /// Time zone code of the logger (offset in 15 minute units)
+    pub timezone: i32,
+    /// Temperature (°C), `NaN` when missing
+    pub t: f64,
+    pub shake: i32,
+    pub err_flag: i32,
+}
+
+impl From<&TmsRecord> for ThermoRecord {
+    fn from(rec: &TmsRecord) -> Self {
+        Self {
+            index: rec.index,
+            datetime: rec.datetime,
+            timezone: rec.timezone,
+            t: rec.t1,
+            shake: rec.shake,
+            err_flag: rec.err_flag,
+        }
+    }
+}
+
+/// One row of a TOMST point dendrometer file.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DendroRecord {
+    /// Record index as written by the logger, `None` for inserted records
+    pub index: Option<u64>,
+    pub datetime: NaiveDateTime,
+    /// Time zone code of the logger (offset in 15 minute units)
+    pub timezone: i32,
+    /// Temperature (°C), `NaN` when missing
+    pub t: f64,
+    /// Raw dendrometer value
+    pub raw: f64,
+    /// Stem radius change (µm), see [`dendro_raw_to_um`]
+    pub um: f64,
+    pub shake: i32,
+    pub err_flag: i32,
+}
+
+impl From<&TmsRecord> for DendroRecord {
+    fn from(rec: &TmsRecord) -> Self {
+        Self {
+            index: rec.index,
+            datetime: rec.datetime,
+            timezone: rec.timezone,
+            t: rec.t1,
+            raw: rec.moisture,
+            um: dendro_raw_to_um(rec.moisture),
+            shake: rec.shake,
+            err_flag: rec.err_flag,
+        }
+    }
+}
+
+/// Typed records of a TOMST file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TomstSeries {
+    Tms(Vec<TmsRecord>),
+    Thermo(Vec<ThermoRecord>),
+    Dendro(Vec<DendroRecord>),
+}
+
+impl TomstSeries {
+    /// Type the records of a file according to `kind`.
+    #[must_use]
+    pub fn from_records(records: Vec<TmsRecord>, kind: TomstLoggerKind) -> Self {
+        match kind {
+            TomstLoggerKind::Tms => TomstSeries::Tms(records),
+            TomstLoggerKind::Thermo => {
+                TomstSeries::Thermo(records.iter().map(ThermoRecord::from).collect())
+            }
+            TomstLoggerKind::Dendro => {
+                TomstSeries::Dendro(records.iter().map(DendroRecord::from).collect())
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn kind(&self) -> TomstLoggerKind {
+        match self {
+            TomstSeries::Tms(_) => TomstLoggerKind::Tms,
+            TomstSeries::Thermo(_) => TomstLoggerKind::Thermo,
+            TomstSeries::Dendro(_) => TomstLoggerKind::Dendro,
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        match self {
+            TomstSeries::Tms(r) => r.len(),
+            TomstSeries::Thermo(r) => r.len(),
+            TomstSeries::Dendro(r) => r.len(),
+        }
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Read a TOMST file of any logger kind, detecting the kind from the
+/// column content.
+///
+/// # Errors
+///
+/// See [`read_tms_file`].
+pub fn read_tomst_file<P: AsRef<Path>>(path: P, options: &TmsReadOptions) -> Result<TomstSeries> {
+    let records = read_tms_file_with_options(path, options)?;
+    let kind = TomstLoggerKind::detect(&records);
+    Ok(TomstSeries::from_records(records, kind))
+}
@@ -23,10 +23,17 @@
  * GNU General Public License for more details.
  */
 
+use crate::calibration::{Calibration, CalibrationSchedule};
+use crate::deployment::Deployment;
+use crate::sensor::{MoistureSensor, SensorReading};
+use crate::soil::SoilParams;
+use crate::tomst::{tz_code_offset, TmsReadOptions, TmsReader, TmsRecord};
 use anyhow::Result;
-use chrono::NaiveDateTime;
-use csv::ReaderBuilder;
+use chrono::{FixedOffset, NaiveDateTime};
 use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum SoilType {
@@ -59,7 +66,7 @@ impl SoilType {
     /// - Wild et al. (2019), 10.1016/j.agrformet.2018.12.018 (soil types 1-9)
     /// - Kopecký et al. (2021), 10.1016/j.scitotenv.2020.143785 (universal)
     /// - Vlček (2010) Kalibrace vlhkostního čidla TST1 (TMS1 variants)
-    fn coeffs(self) -> (f64, f64, f64) {
+    pub(crate) fn coeffs(self) -> (f64, f64, f64) {
         match self {
             SoilType::Sand => (-3.00e-09, 0.000_161_192, -0.109_956_5),
             SoilType::LoamySandA => (-1.90e-08, 0.000_265_610, -0.154_089_3),
@@ -208,11 +215,90 @@ impl TryFrom<&str> for SoilTypeModel {
     }
 }
 
-// myClim temperature correction constants
+// myClim temperature correction constants, defaults of `SoilParams`
 // Source: myClim R package constants
-const REF_T: f64 = 24.0; // Reference temperature (°C)
-const ACOR_T: f64 = 1.911_327; // Temperature correction coefficient A
-const WCOR_T: f64 = 0.64108; // Temperature correction coefficient W
+pub const REF_T: f64 = 24.0; // Reference temperature (°C)
+pub const ACOR_T: f64 = 1.911_327; // Temperature correction coefficient A
+pub const WCOR_T: f64 = 0.64108; // Temperature correction coefficient W
+
+/// TMS temperature sensor driving the VWC temperature correction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TempSensor {
+    /// Soil temperature at -6 cm (myClim default)
+    #[default]
+    T1,
+    /// Surface temperature at +2 cm
+    T2,
+    /// Air temperature at +15 cm
+    T3,
+}
+
+impl TempSensor {
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TempSensor::T1 => "T1",
+            TempSensor::T2 => "T2",
+            TempSensor::T3 => "T3",
+        }
+    }
+
+    /// Temperature of `rec` measured by this sensor.
+    #[must_use]
+    pub fn value(self, rec: &TmsRecord) -> f64 {
+        match self {
+            TempSensor::T1 => rec.t1,
+            TempSensor::T2 => rec.t2,
+            TempSensor::T3 => rec.t3,
+        }
+    }
+}
+
+impl TryFrom<&str> for TempSensor {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s.to_uppercase().as_str() {
+            "T1" => Ok(TempSensor::T1),
+            "T2" => Ok(TempSensor::T2),
+            "T3" => Ok(TempSensor::T3),
+            _ => Err(format!("Unknown temperature sensor: {s}")),
+        }
+    }
+}
+
+/// Parameters of the VWC calculation (myClim `mc_calc_vwc` arguments): the
+/// soil curve with its temperature correction constants, and the sensor
+/// whose temperature is used for the correction.
+///
+/// Converts from [`SoilType`] and [`SoilParams`] with the myClim defaults;
+/// the default is the universal soil type.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VwcParams {
+    pub soil: SoilParams,
+    pub temp_sensor: TempSensor,
+}
+
+impl Default for VwcParams {
+    fn default() -> Self {
+        Self::from(SoilType::Universal)
+    }
+}
+
+impl From<SoilParams> for VwcParams {
+    fn from(soil: SoilParams) -> Self {
+        Self {
+            soil,
+            temp_sensor: TempSensor::default(),
+        }
+    }
+}
+
+impl From<SoilType> for VwcParams {
+    fn from(soil: SoilType) -> Self {
+        Self::from(SoilParams::from(soil))
+    }
+}
 
 /// Calculate VWC using the myClim algorithm
 ///
@@ -226,73 +312,376 @@ const WCOR_T: f64 = 0.64108; // Temperature correction coefficient W
 /// # Arguments
 /// * `raw_value` - Raw moisture sensor reading
 /// * `temp_value` - Temperature reading (°C)
-/// * `soil` - Soil type for coefficient selection
+/// * `soil` - Soil type, custom [`SoilParams`] (see [`crate::SoilRegistry`])
+///   or [`VwcParams`]
 ///
 /// # Returns
 /// Volumetric Water Content (VWC) as a fraction (0.0 to 1.0)
 #[must_use]
-pub fn mc_calc_vwc(raw_value: f64, temp_value: f64, soil: SoilType) -> f64 {
-    let (a, b, c) = soil.coeffs();
+pub fn mc_calc_vwc(raw_value: f64, temp_value: f64, soil: impl Into<VwcParams>) -> f64 {
+    mc_calc_vwc_calibrated(raw_value, temp_value, soil, Calibration::default())
+}
+
+/// Calculate VWC using the myClim algorithm with a field calibration.
+///
+/// Same as [`mc_calc_vwc`], with `cal_cor_factor` and `cal_cor_slope` taken
+/// from `calibration` instead of being 0.
+#[must_use]
+pub fn mc_calc_vwc_calibrated(
+    raw_value: f64,
+    temp_value: f64,
+    soil: impl Into<VwcParams>,
+    calibration: Calibration,
+) -> f64 {
+    let soil = soil.into().soil;
 
     // Step 1: Initial VWC calculation
-    let vwc = a * raw_value * raw_value + b * raw_value + c;
+    let vwc = soil.curve(raw_value);
 
     // Step 2: Temperature correction (from myClim source)
-    let dcor_t = WCOR_T - ACOR_T;
-    let tcor = if temp_value.is_nan() {
-        raw_value
-    } else {
-        raw_value + (REF_T - temp_value) * (ACOR_T + dcor_t * vwc)
-    };
+    let tcor = temperature_corrected(raw_value, temp_value, &soil);
 
     // Step 3: Temperature-corrected VWC calculation
     // Note: cal_cor_factor and cal_cor_slope are 0 for uncalibrated data
-    let cal_cor_factor = 0.0;
-    let cal_cor_slope = 0.0;
-    let corrected_raw = tcor + cal_cor_factor + cal_cor_slope * vwc;
-    let vwc_cor = a * corrected_raw * corrected_raw + b * corrected_raw + c;
+    let corrected_raw = tcor + calibration.cor_factor + calibration.cor_slope * vwc;
+    let vwc_cor = soil.curve(corrected_raw);
 
     // Step 4: Clamp result between 0 and 1 (pmin(pmax(vwc_cor, 0), 1))
     vwc_cor.clamp(0.0, 1.0)
 }
 
+/// Raw count corrected to the reference temperature of `soil` (myClim
+/// `tcor`), the raw count itself when `temp_value` is missing.
+pub(crate) fn temperature_corrected(raw_value: f64, temp_value: f64, soil: &SoilParams) -> f64 {
+    if temp_value.is_nan() {
+        return raw_value;
+    }
+    let dcor_t = soil.wcor_t - soil.acor_t;
+    raw_value + (soil.ref_t - temp_value) * (soil.acor_t + dcor_t * soil.curve(raw_value))
+}
+
+/// Root of `a·x² + b·x + c = 0` on the increasing side of the parabola
+/// (`2a·x + b ≥ 0`), `NaN` without a real root. Falls back to the linear
+/// root when `a` is 0, avoiding the cancellation of the textbook formula.
+fn increasing_root(a: f64, b: f64, c: f64) -> f64 {
+    if a.abs() < f64::EPSILON {
+        return if b.abs() < f64::EPSILON {
+            f64::NAN
+        } else {
+            -c / b
+        };
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return f64::NAN;
+    }
+    let sqrt = discriminant.sqrt();
+    if b >= 0.0 {
+        -2.0 * c / (b + sqrt)
+    } else {
+        (sqrt - b) / (2.0 * a)
+    }
+}
+
+/// Temperature-corrected count at which the curve of `soil` reaches `vwc`,
+/// on its increasing branch; `NaN` when the curve does not reach it.
+pub(crate) fn curve_count(vwc: f64, soil: &SoilParams) -> f64 {
+    increasing_root(soil.a, soil.b, soil.c - vwc)
+}
+
+/// Raw moisture count giving `vwc` at `temp_value` (°C), the inverse of
+/// [`mc_calc_vwc`].
+///
+/// The count is taken on the increasing branch of the soil curve, the
+/// part covered by the sensor; peat, whose curve has a minimum, cannot go
+/// below about 0.16 m³/m³. Since [`mc_calc_vwc`] clamps the VWC, 0 and 1
+/// give the count at which the curve reaches them. Returns `NaN` for a VWC
+/// outside 0 to 1 or out of reach of the curve.
+#[must_use]
+pub fn mc_calc_raw(vwc: f64, temp_value: f64, soil: impl Into<VwcParams>) -> f64 {
+    mc_calc_raw_calibrated(vwc, temp_value, soil, Calibration::default())
+}
+
+/// Same as [`mc_calc_raw`], inverting [`mc_calc_vwc_calibrated`].
+#[must_use]
+pub fn mc_calc_raw_calibrated(
+    vwc: f64,
+    temp_value: f64,
+    soil: impl Into<VwcParams>,
+    calibration: Calibration,
+) -> f64 {
+    if !(0.0..=1.0).contains(&vwc) {
+        return f64::NAN;
+    }
+    let soil = soil.into().soil;
+
+    // Corrected count on the curve: a·r'² + b·r' + c = vwc
+    let corrected_raw = curve_count(vwc, &soil);
+
+    // r' = r + k·(acor_t + dcor_t·curve(r)) + cor_factor + cor_slope·curve(r)
+    // with k = ref_t - temp, a quadratic in r through curve(r)
+    let k = if temp_value.is_nan() {
+        0.0
+    } else {
+        soil.ref_t - temp_value
+    };
+    let m = k * (soil.wcor_t - soil.acor_t) + calibration.cor_slope;
+    increasing_root(
+        m * soil.a,
+        1.0 + m * soil.b,
+        m * soil.c + k * soil.acor_t + calibration.cor_factor - corrected_raw,
+    )
+}
+
+/// TMS loggers as a [`MoistureSensor`]: raw moisture counts read from
+/// TOMST `data_*.csv` files, with the temperature of `temp_sensor` for the
+/// correction and T1 for frozen soil, giving the records of [`VwcReader`].
+impl MoistureSensor for VwcParams {
+    fn name(&self) -> &'static str {
+        "tms"
+    }
+
+    fn vwc(&self, raw: f64, temp: f64, calibration: Calibration) -> f64 {
+        mc_calc_vwc_calibrated(raw, temp, *self, calibration)
+    }
+
+    fn read(&self, reader: &mut dyn Read, options: &TmsReadOptions) -> Result<Vec<SensorReading>> {
+        TmsReader::with_options(reader, *options)
+            .map(|rec| {
+                rec.map(|rec| SensorReading {
+                    datetime: rec.datetime,
+                    timezone: rec.timezone,
+                    raw: rec.moisture,
+                    temp: self.temp_sensor.value(&rec),
+                    soil_temp: rec.t1,
+                })
+            })
+            .collect()
+    }
+}
+
 // ---------------------------------------------------------------------------
 // VWC file processing
 // ---------------------------------------------------------------------------
 
-#[derive(Debug, Deserialize)]
-struct RawRecord {
-    _field0: String,  // index 0
-    datetime: String, // index 1
-    _field2: String,  // index 2
-    temp: f64,        // index 3 - temperature field
-    _field4: String,  // index 4
-    _field5: String,  // index 5
-    raw: f64,         // index 6 - raw count for VWC calculation
-    _field7: String,  // index 7
-    _field8: String,  // index 8
+/// Soil temperature (°C) below which the soil is considered frozen
+/// (myClim `frozen2NA`).
+pub const FROZEN_T: f64 = 0.0;
+
+/// How VWC is reported for records with frozen soil.
+///
+/// TMS moisture counts are meaningless in frozen soil, myClim sets these
+/// values to NA when `frozen2NA = TRUE`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FrozenPolicy {
+    /// Report VWC as computed (myClim `frozen2NA = FALSE`)
+    #[default]
+    Keep,
+    /// Report VWC as missing (`NaN`, myClim `frozen2NA = TRUE`)
+    Missing,
+    /// Report VWC as computed and mark the record as frozen
+    Flag,
+}
+
+impl FrozenPolicy {
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FrozenPolicy::Keep => "keep",
+            FrozenPolicy::Missing => "na",
+            FrozenPolicy::Flag => "flag",
+        }
+    }
+}
+
+impl TryFrom<&str> for FrozenPolicy {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        match s.to_lowercase().as_str() {
+            "keep" => Ok(FrozenPolicy::Keep),
+            "na" | "missing" => Ok(FrozenPolicy::Missing),
+            "flag" => Ok(FrozenPolicy::Flag),
+            _ => Err(format!("Unknown frozen soil policy: {s}")),
+        }
+    }
+}
+
+/// Options controlling how [`process_file_with_options`] computes VWC.
+#[derive(Debug, Clone, Default)]
+pub struct VwcOptions {
+    /// Calibration applied to the moisture counts (none by default)
+    pub calibration: CalibrationSchedule,
+    /// Handling of records with soil temperature below [`FROZEN_T`]
+    pub frozen: FrozenPolicy,
+    /// Time zone handling when reading the TOMST file
+    pub read: TmsReadOptions,
+    /// Records outside the deployment period (compared with the datetimes
+    /// after time zone conversion) are dropped
+    pub deployment: Deployment,
+}
+
+/// A TMS record with its computed VWC.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VwcRecord {
+    pub datetime: NaiveDateTime,
+    /// Time zone code of `datetime` (offset in 15 minute units)
+    pub timezone: i32,
+    /// Raw moisture count
+    pub raw: f64,
+    /// Temperature (°C) used for the temperature correction
+    pub temp: f64,
+    /// Volumetric water content, `NaN` when missing
+    pub vwc: f64,
+    /// Soil temperature below [`FROZEN_T`]
+    pub frozen: bool,
+}
+
+/// Logger serial number of a TOMST file, e.g. `94184102` for
+/// `data_94184102_2023_09_20_0.csv`. Falls back to the file stem when the
+/// name does not follow the TOMST pattern.
+#[must_use]
+pub fn logger_serial_from_path<P: AsRef<Path>>(path: P) -> Option<String> {
+    let stem = path.as_ref().file_stem()?.to_str()?;
+    let serial = stem
+        .strip_prefix("data_")
+        .and_then(|rest| rest.split('_').next())
+        .filter(|s| !s.is_empty())
+        .unwrap_or(stem);
+    Some(serial.to_string())
 }
 
-/// Read `<path>`, compute VWC for `soil`, return (datetime, raw, temp, vwc).
+/// Read `<path>`, compute VWC for `soil`, return one [`VwcRecord`] per row.
 ///
 /// # Errors
 ///
 /// This function returns an error if:
 /// - The file at `path` cannot be opened or read
 /// - CSV parsing fails due to invalid format
-/// - `DateTime` parsing fails (expects format: "%Y.%m.%d %H:%M")
+/// - `DateTime` parsing fails: one of the [`crate::TomstDateFormat`] layouts
+///   is detected from the first record, unless set in
+///   [`crate::TmsReadOptions::date_format`]
 /// - Any field deserialization fails
-pub fn process_file(path: String, soil: SoilType) -> Result<Vec<(NaiveDateTime, f64, f64, f64)>> {
-    let mut rdr = ReaderBuilder::new()
-        .delimiter(b';')
-        .has_headers(false)
-        .from_path(path)?;
-    let mut out = Vec::new();
-    for result in rdr.deserialize() {
-        let rec: RawRecord = result?;
-        let dt = NaiveDateTime::parse_from_str(&rec.datetime, "%Y.%m.%d %H:%M")?;
-        let vwc = mc_calc_vwc(rec.raw, rec.temp, soil);
-        out.push((dt, rec.raw, rec.temp, vwc));
+///
+/// Use [`crate::read_tms_file`] to access all columns of the file, and
+/// [`VwcReader`] to process large files without loading them in memory.
+pub fn process_file(path: String, soil: impl Into<VwcParams>) -> Result<Vec<VwcRecord>> {
+    process_file_with_options(path, soil, &VwcOptions::default())
+}
+
+/// Same as [`process_file`], applying the calibration, frozen soil policy
+/// and time zone settings from `options`.
+///
+/// # Errors
+///
+/// See [`process_file`].
+pub fn process_file_with_options(
+    path: String,
+    soil: impl Into<VwcParams>,
+    options: &VwcOptions,
+) -> Result<Vec<VwcRecord>> {
+    VwcReader::new(File::open(path)?, soil, options.clone()).collect()
+}
+
+/// Streaming VWC computation over a TOMST TMS file from any reader.
+///
+/// Rows are parsed and converted one at a time, so memory use does not grow
+/// with the file size. Concatenated files are read as a single series.
+pub struct VwcReader<R: Read> {
+    tms: TmsReader<R>,
+    params: VwcParams,
+    options: VwcOptions,
+}
+
+impl<R: Read> VwcReader<R> {
+    pub fn new(reader: R, soil: impl Into<VwcParams>, options: VwcOptions) -> Self {
+        Self {
+            tms: TmsReader::with_options(reader, options.read),
+            params: soil.into(),
+            options,
+        }
+    }
+}
+
+impl VwcReader<File> {
+    /// Open the TOMST file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened.
+    pub fn from_path<P: AsRef<Path>>(
+        path: P,
+        soil: impl Into<VwcParams>,
+        options: VwcOptions,
+    ) -> Result<Self> {
+        Ok(Self::new(File::open(path)?, soil, options))
+    }
+}
+
+impl<R: Read> Iterator for VwcReader<R> {
+    type Item = Result<VwcRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.tms.next()? {
+                Ok(rec) if !self.options.deployment.contains(rec.datetime) => {}
+                rec => {
+                    return Some(
+                        rec.map(|rec| VwcRecord::from_tms(&rec, self.params, &self.options)),
+                    )
+                }
+            }
+        }
+    }
+}
+
+/// Compute VWC for TMS records already read with [`crate::TmsReader`].
+#[must_use]
+pub fn process_records(
+    records: &[TmsRecord],
+    soil: impl Into<VwcParams>,
+    options: &VwcOptions,
+) -> Vec<VwcRecord> {
+    let soil = soil.into();
+    records
+        .iter()
+        .filter(|rec| options.deployment.contains(rec.datetime))
+        .map(|rec| VwcRecord::from_tms(rec, soil, options))
+        .collect()
+}
+
+impl VwcRecord {
+    /// UTC offset of `datetime`.
+    #[must_use]
+    pub fn utc_offset(&self) -> FixedOffset {
+        tz_code_offset(self.timezone)
+    }
+
+    /// Compute VWC of a single TMS record, using the temperature of
+    /// `soil.temp_sensor` (T1 by default) for the correction. Frozen soil is
+    /// always detected from T1.
+    #[must_use]
+    pub fn from_tms(rec: &TmsRecord, soil: impl Into<VwcParams>, options: &VwcOptions) -> Self {
+        let params = soil.into();
+        let temp = params.temp_sensor.value(rec);
+        let vwc = mc_calc_vwc_calibrated(
+            rec.moisture,
+            temp,
+            params,
+            options.calibration.at(rec.datetime),
+        );
+        let frozen = rec.t1 < FROZEN_T;
+        Self {
+            datetime: rec.datetime,
+            timezone: rec.timezone,
+            raw: rec.moisture,
+            temp,
+            vwc: if frozen && options.frozen == FrozenPolicy::Missing {
+                f64::NAN
+            } else {
+                vwc
+            },
+            frozen,
+        }
     }
-    Ok(out)
 }
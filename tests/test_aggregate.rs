@@ -0,0 +1,212 @@
+use chrono::{NaiveDateTime, TimeDelta};
+use soil_sensor_toolbox::{
+    mc_agg, quantile, read_tms_file, AggFunction, AggOptions, AggVariable, Period, TmsRecord,
+};
+
+fn dt(s: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+}
+
+/// Hourly series starting at `start` with T1 = 0, 1, 2, ...
+fn hourly(start: &str, hours: u32) -> Vec<TmsRecord> {
+    (0..hours)
+        .map(|i| TmsRecord {
+            index: Some(u64::from(i)),
+            datetime: dt(start) + TimeDelta::hours(i64::from(i)),
+            timezone: 0,
+            t1: f64::from(i),
+            t2: f64::NAN,
+            t3: f64::NAN,
+            moisture: 1000.0,
+            shake: 0,
+            err_flag: 0,
+        })
+        .collect()
+}
+
+fn t1() -> AggVariable<'static, TmsRecord> {
+    ("T1", &|r: &TmsRecord| r.t1)
+}
+
+#[test]
+fn test_daily_aggregation() {
+    let records = hourly("2024-01-01 00:00", 48);
+    let options = AggOptions {
+        functions: vec![
+            AggFunction::Min,
+            AggFunction::Max,
+            AggFunction::Mean,
+            AggFunction::Percentile(50.0),
+        ],
+        ..AggOptions::default()
+    };
+    let table = mc_agg(&records, &[t1()], &options).unwrap();
+
+    assert_eq!(
+        table.columns,
+        ["T1_min", "T1_max", "T1_mean", "T1_percentile50"]
+    );
+    assert_eq!(table.rows.len(), 2);
+    assert_eq!(table.rows[1].start, dt("2024-01-02 00:00"));
+    assert_eq!(table.rows[1].end, dt("2024-01-03 00:00"));
+    assert_eq!(table.column("T1_min").unwrap(), [0.0, 24.0]);
+    assert_eq!(table.column("T1_max").unwrap(), [23.0, 47.0]);
+    assert_eq!(table.column("T1_mean").unwrap(), [11.5, 35.5]);
+    assert_eq!(table.column("T1_percentile50").unwrap(), [11.5, 35.5]);
+}
+
+#[test]
+fn test_min_coverage() {
+    // Day 1 complete, day 2 has 12 of 24 hours
+    let mut records = hourly("2024-01-01 00:00", 36);
+    records[5].t1 = f64::NAN;
+    let functions = vec![AggFunction::Mean, AggFunction::Coverage, AggFunction::Count];
+
+    let strict = AggOptions {
+        functions: functions.clone(),
+        ..AggOptions::default()
+    };
+    let table = mc_agg(&records, &[t1()], &strict).unwrap();
+    let mean = table.column("T1_mean").unwrap();
+    assert!(mean[0].is_nan() && mean[1].is_nan());
+    assert_eq!(table.column("T1_count").unwrap(), [23.0, 12.0]);
+    let coverage = table.column("T1_coverage").unwrap();
+    assert!((coverage[0] - 23.0 / 24.0).abs() < 1e-12);
+    assert!((coverage[1] - 0.5).abs() < 1e-12);
+
+    let lenient = AggOptions {
+        functions,
+        min_coverage: 0.9,
+        ..AggOptions::default()
+    };
+    let mean = mc_agg(&records, &[t1()], &lenient)
+        .unwrap()
+        .column("T1_mean")
+        .unwrap();
+    assert!((mean[0] - (276.0 - 5.0) / 23.0).abs() < 1e-12);
+    assert!(mean[1].is_nan());
+}
+
+#[test]
+fn test_periods() {
+    // 2024-01-01 is a Monday
+    let records = hourly("2023-12-31 00:00", 24 * 40);
+    let count = |period| {
+        let options = AggOptions {
+            period,
+            functions: vec![AggFunction::Count],
+            ..AggOptions::default()
+        };
+        let table = mc_agg(&records, &[t1()], &options).unwrap();
+        table
+            .rows
+            .iter()
+            .map(|r| (r.start, r.values[0]))
+            .collect::<Vec<_>>()
+    };
+
+    let weeks = count(Period::Week);
+    assert_eq!(weeks[0], (dt("2023-12-25 00:00"), 24.0));
+    assert_eq!(weeks[1], (dt("2024-01-01 00:00"), 168.0));
+
+    let months = count(Period::Month);
+    assert_eq!(months.len(), 3);
+    assert_eq!(months[1], (dt("2024-01-01 00:00"), 24.0 * 31.0));
+
+    let years = count(Period::Year);
+    assert_eq!(
+        years,
+        [
+            (dt("2023-01-01 00:00"), 24.0),
+            (dt("2024-01-01 00:00"), 24.0 * 39.0)
+        ]
+    );
+
+    assert_eq!(count(Period::Hour).len(), 24 * 40);
+    assert_eq!(count(Period::All), [(dt("2023-12-31 00:00"), 24.0 * 40.0)]);
+
+    // Winter window across the new year, records outside it are skipped
+    let custom = Period::try_from("custom:12-31:01-02").unwrap();
+    assert_eq!(count(custom), [(dt("2023-12-31 00:00"), 72.0)]);
+}
+
+#[test]
+fn test_custom_period_leap_day() {
+    let options = |period: &str| AggOptions {
+        period: Period::try_from(period).unwrap(),
+        functions: vec![AggFunction::Count],
+        ..AggOptions::default()
+    };
+    // 2023 is a common year, the window ends on 02-28
+    let records = hourly("2023-02-20 00:00", 24 * 14);
+    let table = mc_agg(&records, &[t1()], &options("custom:02-25:02-29")).unwrap();
+    assert_eq!(table.rows.len(), 1);
+    assert_eq!(table.rows[0].start, dt("2023-02-25 00:00"));
+    assert_eq!(table.rows[0].end, dt("2023-03-01 00:00"));
+    assert!((table.rows[0].values[0] - 24.0 * 4.0).abs() < f64::EPSILON);
+
+    // and starts on 02-28
+    let table = mc_agg(&records, &[t1()], &options("custom:02-29:03-02")).unwrap();
+    assert_eq!(table.rows[0].start, dt("2023-02-28 00:00"));
+    assert!((table.rows[0].values[0] - 24.0 * 3.0).abs() < f64::EPSILON);
+
+    // 2024 is a leap year
+    let records = hourly("2024-02-20 00:00", 24 * 14);
+    let table = mc_agg(&records, &[t1()], &options("custom:02-25:02-29")).unwrap();
+    assert_eq!(table.rows[0].end, dt("2024-03-01 00:00"));
+    assert!((table.rows[0].values[0] - 24.0 * 5.0).abs() < f64::EPSILON);
+}
+
+#[test]
+fn test_parse_options() {
+    assert_eq!(Period::try_from("Month"), Ok(Period::Month));
+    assert_eq!(
+        Period::try_from("custom:05-01:09-30"),
+        Ok(Period::Custom {
+            start: (5, 1),
+            end: (9, 30)
+        })
+    );
+    assert!(Period::try_from("custom:13-01:09-30").is_err());
+    assert!(Period::try_from("fortnight").is_err());
+    assert_eq!(
+        AggFunction::try_from("percentile95"),
+        Ok(AggFunction::Percentile(95.0))
+    );
+    assert_eq!(AggFunction::Percentile(5.0).name(), "percentile5");
+    assert!(AggFunction::try_from("median").is_err());
+}
+
+#[test]
+fn test_quantile() {
+    // R: quantile(c(1, 2, 3, 4, 10), c(0, 0.1, 0.5, 0.95, 1))
+    let values = [4.0, 1.0, f64::NAN, 3.0, 10.0, 2.0];
+    assert!((quantile(&values, 0.0) - 1.0).abs() < 1e-12);
+    assert!((quantile(&values, 0.1) - 1.4).abs() < 1e-12);
+    assert!((quantile(&values, 0.5) - 3.0).abs() < 1e-12);
+    assert!((quantile(&values, 0.95) - 8.8).abs() < 1e-12);
+    assert!((quantile(&values, 1.0) - 10.0).abs() < 1e-12);
+    assert!(quantile(&[f64::NAN], 0.5).is_nan());
+}
+
+#[test]
+fn test_aggregate_tms_file() {
+    let records = read_tms_file("tests/fixtures/data/data_winter.csv").unwrap();
+    let options = AggOptions {
+        functions: vec![AggFunction::Min, AggFunction::Count],
+        ..AggOptions::default()
+    };
+    let vars: [AggVariable<'_, TmsRecord>; 2] = [t1(), ("moisture", &|r| r.moisture)];
+    let table = mc_agg(&records, &vars, &options).unwrap();
+
+    // 21 full days of 15 minute records
+    assert_eq!(table.rows.len(), 21);
+    assert_eq!(
+        table.columns,
+        ["T1_min", "T1_count", "moisture_min", "moisture_count"]
+    );
+    assert!(table
+        .rows
+        .iter()
+        .all(|r| (r.values[1] - 96.0).abs() < 1e-12));
+}
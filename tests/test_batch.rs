@@ -0,0 +1,161 @@
+use chrono::NaiveDateTime;
+use soil_sensor_toolbox::{
+    find_tms_files, locality_file_stem, process_batch, process_batch_with, process_file,
+    BatchOptions, CalibrationTable, MetadataTable, SoilType,
+};
+
+const DIR: &str = "tests/fixtures/batch";
+
+fn dt(s: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+}
+
+#[test]
+fn test_metadata_table() {
+    let table = MetadataTable::from_path(format!("{DIR}/metadata.csv")).unwrap();
+    assert_eq!(table.entries.len(), 2);
+
+    let a = table.get("94184102").unwrap();
+    assert_eq!(a.locality, "LOC_A");
+    assert_eq!(a.soil.as_deref(), Some("loam"));
+    assert_eq!(a.latitude, Some(46.52));
+    assert_eq!(a.installed, Some(dt("2024-01-10 00:00")));
+    assert_eq!(a.removed, None);
+
+    let b = table.get("94184103").unwrap();
+    assert_eq!(b.installed, Some(dt("2024-01-09 00:00")));
+    assert_eq!(b.removed, Some(dt("2024-02-01 00:00")));
+    assert!(table.get("94184199").is_none());
+}
+
+#[test]
+fn test_locality_file_stem() {
+    assert_eq!(locality_file_stem("LOC_A"), "loc_a");
+    assert_eq!(locality_file_stem("../x"), "___x");
+    assert_eq!(locality_file_stem("a/b\\c"), "a_b_c");
+    assert_eq!(locality_file_stem("C:"), "c_");
+    assert_eq!(locality_file_stem("Forêt-2"), "for_t-2");
+    assert_eq!(locality_file_stem("Site A"), locality_file_stem("site a"));
+}
+
+#[test]
+fn test_find_tms_files() {
+    let files = find_tms_files(DIR).unwrap();
+    let names: Vec<_> = files
+        .iter()
+        .map(|p| p.file_name().unwrap().to_str().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "data_94184102_2024_01_11_0.csv",
+            "data_94184103_2024_01_11_0.csv",
+            "data_94184199_2024_01_11_0.csv"
+        ]
+    );
+}
+
+#[test]
+fn test_process_batch() {
+    let files = find_tms_files(DIR).unwrap();
+    let metadata = MetadataTable::from_path(format!("{DIR}/metadata.csv")).unwrap();
+    let options = BatchOptions {
+        threads: 2,
+        ..BatchOptions::default()
+    };
+    let results = process_batch(&files, &metadata, &options);
+    assert_eq!(results.len(), 3);
+
+    // Same VWC as processing the file alone with the soil of the metadata
+    let a = results[0].as_ref().unwrap();
+    assert_eq!(a.serial, "94184102");
+    assert_eq!(a.locality(), "LOC_A");
+    let expected = process_file(files[0].to_str().unwrap().to_string(), SoilType::Loam).unwrap();
+    assert_eq!(a.records, expected);
+
+    let b = results[1].as_ref().unwrap();
+    assert_eq!(b.locality(), "LOC_B");
+    assert_eq!(b.records.len(), 48);
+
+    // Unknown logger without a default soil type
+    let err = results[2].as_ref().unwrap_err();
+    assert!(err.to_string().contains("No soil type for logger 94184199"));
+
+    let with_default = BatchOptions {
+        default_soil: Some("universal".to_string()),
+        ..BatchOptions::default()
+    };
+    let results = process_batch(&files, &metadata, &with_default);
+    let c = results[2].as_ref().unwrap();
+    assert_eq!(c.locality(), "94184199");
+    assert!(c.metadata.is_none());
+    assert_eq!(c.records.len(), 10);
+}
+
+#[test]
+fn test_process_batch_with() {
+    // More files than the chunk of one thread
+    let files = find_tms_files(DIR).unwrap();
+    let files: Vec<_> = files.iter().chain(&files).cloned().collect();
+    let metadata = MetadataTable::from_path(format!("{DIR}/metadata.csv")).unwrap();
+    let options = BatchOptions {
+        threads: 1,
+        default_soil: Some("universal".to_string()),
+        ..BatchOptions::default()
+    };
+    let mut seen = Vec::new();
+    process_batch_with(&files, &metadata, &options, |path, result| {
+        let series = result?;
+        assert_eq!(series.path, path);
+        seen.push((series.locality().to_string(), series.records.len()));
+        Ok(())
+    })
+    .unwrap();
+    let expected: Vec<_> = process_batch(&files, &metadata, &options)
+        .into_iter()
+        .map(|r| {
+            r.map(|s| (s.locality().to_string(), s.records.len()))
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(seen, expected);
+    assert_eq!(metadata.locality("94184103"), "LOC_B");
+    assert_eq!(metadata.locality("94184199"), "94184199");
+
+    // The first error of the sink stops the batch
+    let mut calls = 0;
+    let result = process_batch_with(&files, &metadata, &options, |_, _| {
+        calls += 1;
+        anyhow::bail!("disk full")
+    });
+    assert!(result.is_err());
+    assert_eq!(calls, 1);
+}
+
+#[test]
+fn test_batch_calibration_by_locality() {
+    let files = find_tms_files(DIR).unwrap();
+    let metadata = MetadataTable::from_path(format!("{DIR}/metadata.csv")).unwrap();
+    let table = CalibrationTable::from_reader(
+        "locality_id,datetime,cor_factor,cor_slope\nLOC_B,2024-01-01 00:00,300,0\n".as_bytes(),
+    )
+    .unwrap();
+    let plain = process_batch(&files[..2], &metadata, &BatchOptions::default());
+    let calibrated = process_batch(
+        &files[..2],
+        &metadata,
+        &BatchOptions {
+            calibration: Some(table),
+            ..BatchOptions::default()
+        },
+    );
+
+    let (plain_a, cal_a) = (plain[0].as_ref().unwrap(), calibrated[0].as_ref().unwrap());
+    assert_eq!(plain_a.records, cal_a.records);
+    let (plain_b, cal_b) = (plain[1].as_ref().unwrap(), calibrated[1].as_ref().unwrap());
+    assert!(plain_b
+        .records
+        .iter()
+        .zip(&cal_b.records)
+        .all(|(p, c)| c.vwc > p.vwc));
+}
@@ -0,0 +1,164 @@
+use chrono::{NaiveDateTime, TimeDelta};
+use soil_sensor_toolbox::{
+    fit_logger_calibration, mc_calc_vwc, mc_calc_vwc_calibrated, mc_calib_moisture, process_file,
+    read_reference_samples_from, Calibration, CalibrationTable, SoilParams, SoilType, TempSensor,
+    VwcParams,
+};
+
+fn dt(s: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+}
+
+const TABLE: &str = "\
+serial_number,sensor_id,datetime,cor_factor,cor_slope
+94184102,TMS_moist,2023-07-01 00:00:00,120,0
+94184102,TMS_T1,2023-07-01 00:00:00,0.5,0
+94184102,TMS_moist,2023-10-01,-80,250
+94184103,TMS_moist,2023-05-01 12:00,10,0
+";
+
+#[test]
+fn test_schedule_validity_ranges() {
+    let table = CalibrationTable::from_reader(TABLE.as_bytes()).unwrap();
+    // The TMS_T1 row is not a moisture calibration
+    assert_eq!(table.entries.len(), 3);
+
+    let schedule = table.schedule("94184102");
+    assert_eq!(schedule.at(dt("2023-06-30 23:45")), Calibration::default());
+    assert_eq!(
+        schedule.at(dt("2023-07-01 00:00")),
+        Calibration {
+            cor_factor: 120.0,
+            cor_slope: 0.0
+        }
+    );
+    assert_eq!(
+        schedule.at(dt("2024-01-01 00:00")),
+        Calibration {
+            cor_factor: -80.0,
+            cor_slope: 250.0
+        }
+    );
+    assert!(table.schedule("unknown").is_empty());
+}
+
+#[test]
+fn test_calibration_shifts_vwc() {
+    let uncalibrated = mc_calc_vwc(1500.0, 12.0, SoilType::Loam);
+    let zero = mc_calc_vwc_calibrated(1500.0, 12.0, SoilType::Loam, Calibration::default());
+    assert!((uncalibrated - zero).abs() < f64::EPSILON);
+
+    let wetter = mc_calc_vwc_calibrated(
+        1500.0,
+        12.0,
+        SoilType::Loam,
+        Calibration {
+            cor_factor: 100.0,
+            cor_slope: 0.0,
+        },
+    );
+    assert!(wetter > uncalibrated, "{wetter} <= {uncalibrated}");
+}
+
+#[test]
+fn test_fit_recovers_known_calibration() {
+    let truth = Calibration {
+        cor_factor: 80.0,
+        cor_slope: -150.0,
+    };
+    let raw = [1200.0, 1600.0, 2100.0, 2600.0, 3000.0];
+    let temp = [5.0, 12.0, 18.0, 9.0, 21.0];
+    let ref_vwc: Vec<f64> = raw
+        .iter()
+        .zip(&temp)
+        .map(|(r, t)| mc_calc_vwc_calibrated(*r, *t, SoilType::Universal, truth))
+        .collect();
+
+    let fit = mc_calib_moisture(&raw, &temp, &ref_vwc, SoilType::Universal).unwrap();
+    assert!((fit.calibration.cor_factor - truth.cor_factor).abs() < 1e-6);
+    assert!((fit.calibration.cor_slope - truth.cor_slope).abs() < 1e-6);
+    assert!(fit.rmse < 1e-9, "rmse {}", fit.rmse);
+    assert_eq!(fit.residuals.len(), raw.len());
+}
+
+#[test]
+fn test_fit_custom_soil() {
+    // Site curve with its own temperature correction, driven by T3
+    let params = VwcParams {
+        soil: SoilParams {
+            ref_t: 20.0,
+            acor_t: 1.5,
+            wcor_t: 0.9,
+            ..SoilParams::new(-2.0e-8, 2.8e-4, -0.18)
+        },
+        temp_sensor: TempSensor::T3,
+    };
+    let truth = Calibration {
+        cor_factor: -60.0,
+        cor_slope: 200.0,
+    };
+    let raw = [1300.0, 1700.0, 2200.0, 2500.0];
+    let temp = [2.0, 15.0, 25.0, 8.0];
+    let ref_vwc: Vec<f64> = raw
+        .iter()
+        .zip(&temp)
+        .map(|(r, t)| mc_calc_vwc_calibrated(*r, *t, params, truth))
+        .collect();
+
+    let fit = mc_calib_moisture(&raw, &temp, &ref_vwc, params).unwrap();
+    assert!((fit.calibration.cor_factor - truth.cor_factor).abs() < 1e-6);
+    assert!((fit.calibration.cor_slope - truth.cor_slope).abs() < 1e-6);
+    assert!(fit.rmse < 1e-9, "rmse {}", fit.rmse);
+
+    assert!(mc_calib_moisture(&[1500.0], &[10.0], &[0.9], params).is_err());
+}
+
+#[test]
+fn test_fit_from_matched_samples() {
+    let series = process_file("tests/fixtures/data/data.csv".to_string(), SoilType::Loam).unwrap();
+    let samples = read_reference_samples_from(
+        "\
+logger,datetime,vwc
+data,2023-06-15 10:05:00,0.25
+data,2023-09-20 14:00:00,0.31
+data,2023-09-20 14:00:00,0.30
+other,2023-09-20 14:00:00,0.10
+data,2030-01-01 00:00:00,0.40
+"
+        .as_bytes(),
+    )
+    .unwrap();
+
+    let (matched, fit) = fit_logger_calibration(
+        &samples,
+        "data",
+        &series,
+        SoilType::Loam,
+        TimeDelta::minutes(30),
+    )
+    .unwrap();
+    // The other logger and the sample outside the series are not matched
+    assert_eq!(matched.len(), 3);
+    assert_eq!(matched[0].record_datetime, dt("2023-06-15 10:00"));
+    assert!(fit.rmse < 0.01, "rmse {}", fit.rmse);
+}
+
+#[test]
+fn test_reference_sample_datetimes() {
+    // Same layouts as calibration tables
+    let samples = read_reference_samples_from(
+        "\
+logger,datetime,vwc
+data,2023-06-15,0.25
+data,2023.06.15 10:05,0.25
+"
+        .as_bytes(),
+    )
+    .unwrap();
+    assert_eq!(samples[0].datetime, dt("2023-06-15 00:00"));
+    assert_eq!(samples[1].datetime, dt("2023-06-15 10:05"));
+    assert!(
+        read_reference_samples_from("logger,datetime,vwc\ndata,15/06/2023,0.2\n".as_bytes())
+            .is_err()
+    );
+}
@@ -0,0 +1,75 @@
+use chrono::{NaiveDateTime, TimeDelta};
+use soil_sensor_toolbox::{clean, detect_step, read_tms_file, CleanOptions};
+
+fn dt(s: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+}
+
+#[test]
+fn test_clean_report() {
+    let records = read_tms_file("tests/fixtures/clean/data_unclean.csv").unwrap();
+    let (cleaned, report) = clean(&records, &CleanOptions::default()).unwrap();
+
+    assert_eq!(report.step_minutes, 15);
+    assert_eq!(report.start_date, dt("2023-06-01 00:00"));
+    assert_eq!(report.end_date, dt("2023-06-01 02:15"));
+    assert_eq!(report.count_rounded, 1);
+    assert_eq!(report.count_disordered, 1);
+    assert_eq!(report.count_duplicities, 1);
+    assert_eq!(report.count_missing, 2);
+    assert_eq!(
+        report.gaps,
+        vec![(dt("2023-06-01 01:15"), dt("2023-06-01 02:00"))]
+    );
+
+    // Regular 15 minute series from 00:00 to 02:15
+    assert_eq!(cleaned.len(), 10);
+    for (i, rec) in cleaned.iter().enumerate() {
+        assert_eq!(
+            rec.datetime,
+            dt("2023-06-01 00:00") + TimeDelta::minutes(15 * i64::try_from(i).unwrap())
+        );
+    }
+    // The first of the duplicated 01:00 records is kept
+    assert!((cleaned[4].moisture - 1504.0).abs() < f64::EPSILON);
+    // Gap records have missing values and no logger index
+    assert!(cleaned[6].moisture.is_nan() && cleaned[7].t1.is_nan());
+    assert_eq!((cleaned[6].index, cleaned[7].index), (None, None));
+    assert!(cleaned[5].index.is_some());
+}
+
+#[test]
+fn test_clean_without_filling() {
+    let records = read_tms_file("tests/fixtures/clean/data_unclean.csv").unwrap();
+    let options = CleanOptions {
+        fill_gaps: false,
+        round: false,
+        ..CleanOptions::default()
+    };
+    let (cleaned, report) = clean(&records, &options).unwrap();
+    assert_eq!(report.count_rounded, 0);
+    assert_eq!(report.count_missing, 2);
+    assert_eq!(cleaned.len(), 8);
+    assert_eq!(cleaned[2].datetime, dt("2023-06-01 00:31"));
+}
+
+#[test]
+fn test_clean_regular_file_is_unchanged() {
+    let records = read_tms_file("tests/fixtures/data/data.csv").unwrap();
+    let (cleaned, report) = clean(&records, &CleanOptions::default()).unwrap();
+    assert_eq!(report.step_minutes, 15);
+    assert_eq!(report.count_duplicities + report.count_missing, 0);
+    assert_eq!(cleaned, records);
+}
+
+#[test]
+fn test_detect_step() {
+    let datetimes = [
+        dt("2023-06-01 00:00"),
+        dt("2023-06-01 00:10"),
+        dt("2023-06-01 00:20"),
+        dt("2023-06-01 00:35"),
+    ];
+    assert_eq!(detect_step(&datetimes), Some(TimeDelta::minutes(10)));
+    assert_eq!(detect_step(&datetimes[..1]), None);
+}
@@ -0,0 +1,125 @@
+use chrono::{NaiveDateTime, TimeDelta};
+use soil_sensor_toolbox::{
+    detect_deployment, find_tms_files, process_batch, process_file_with_options, read_tms_file,
+    trim_to_deployment, BatchOptions, Deployment, DetectOptions, MetadataTable, SoilType,
+    VwcOptions, VwcReader,
+};
+
+const DIR: &str = "tests/fixtures/deployment";
+const FILE: &str = "tests/fixtures/deployment/data_94190001_2024_05_09_0.csv";
+
+fn dt(s: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+}
+
+fn expected() -> Deployment {
+    Deployment {
+        installed: Some(dt("2024-05-02 10:00")),
+        removed: Some(dt("2024-05-07 15:45")),
+    }
+}
+
+#[test]
+fn test_detect_deployment() {
+    let records = read_tms_file(FILE).unwrap();
+    assert_eq!(
+        detect_deployment(&records, &DetectOptions::default()),
+        expected()
+    );
+
+    // Only the installation is in the series
+    let installed_only: Vec<_> = records
+        .iter()
+        .filter(|r| r.datetime < dt("2024-05-06 00:00"))
+        .copied()
+        .collect();
+    assert_eq!(
+        detect_deployment(&installed_only, &DetectOptions::default()),
+        Deployment {
+            installed: expected().installed,
+            removed: None,
+        }
+    );
+
+    // No jump large enough
+    let strict = DetectOptions {
+        min_moisture_jump: 5000.0,
+        ..DetectOptions::default()
+    };
+    assert!(detect_deployment(&records, &strict).is_open());
+}
+
+#[test]
+fn test_no_deployment_in_soil_series() {
+    let records = read_tms_file("tests/fixtures/data/data_winter.csv").unwrap();
+    let short = DetectOptions {
+        window: TimeDelta::hours(6),
+        ..DetectOptions::default()
+    };
+    // Thawing raises the moisture count, but not from the level of air
+    assert!(detect_deployment(&records, &DetectOptions::default()).is_open());
+    assert!(detect_deployment(&records, &short).is_open());
+    assert!(detect_deployment(&records[..10], &DetectOptions::default()).is_open());
+}
+
+#[test]
+fn test_trim_to_deployment() {
+    let records = read_tms_file(FILE).unwrap();
+    let trimmed = trim_to_deployment(&records, &expected());
+    assert_eq!(trimmed.first().unwrap().datetime, dt("2024-05-02 10:00"));
+    assert_eq!(trimmed.last().unwrap().datetime, dt("2024-05-07 15:45"));
+    assert!(trimmed.iter().all(|r| r.moisture > 1500.0));
+
+    let open = trim_to_deployment(&records, &Deployment::default());
+    assert_eq!(open.len(), records.len());
+}
+
+#[test]
+fn test_process_file_in_deployment() {
+    let options = VwcOptions {
+        deployment: expected(),
+        ..VwcOptions::default()
+    };
+    let records =
+        process_file_with_options(FILE.to_string(), SoilType::Universal, &options).unwrap();
+    // 5 days and 6 hours of 15 minute records
+    assert_eq!(records.len(), (5 * 24 + 6) * 4);
+    assert_eq!(records[0].datetime, dt("2024-05-02 10:00"));
+
+    let streamed = VwcReader::from_path(FILE, SoilType::Universal, options)
+        .unwrap()
+        .collect::<anyhow::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(records, streamed);
+}
+
+#[test]
+fn test_batch_deployment() {
+    let files = find_tms_files(DIR).unwrap();
+    let metadata = MetadataTable::from_reader(
+        "serial,locality,soil,installed\n94190001,LOC_C,loam,2024-05-03 00:00\n".as_bytes(),
+    )
+    .unwrap();
+
+    // Metadata only
+    let results = process_batch(&files, &metadata, &BatchOptions::default());
+    let series = results[0].as_ref().unwrap();
+    assert_eq!(series.records[0].datetime, dt("2024-05-03 00:00"));
+    assert_eq!(
+        series.records.last().unwrap().datetime,
+        dt("2024-05-08 23:45")
+    );
+
+    // Missing removal detected
+    let detect = BatchOptions {
+        detect_deployment: Some(DetectOptions::default()),
+        ..BatchOptions::default()
+    };
+    let results = process_batch(&files, &metadata, &detect);
+    let series = results[0].as_ref().unwrap();
+    assert_eq!(series.records[0].datetime, dt("2024-05-03 00:00"));
+    assert_eq!(
+        series.records.last().unwrap().datetime,
+        dt("2024-05-07 15:45")
+    );
+}
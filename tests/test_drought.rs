@@ -0,0 +1,91 @@
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+use soil_sensor_toolbox::{drought_stats, DroughtOptions, Period, VwcRecord};
+
+fn date(s: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+}
+
+fn assert_close(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-9, "{a} != {b}");
+}
+
+/// Ten days of hourly VWC from 2024-07-01: wet days 1, 2 and 6, dry days 3
+/// to 5, 8 and 10, day 7 wet in the morning and dry in the afternoon, day 9
+/// missing.
+fn series() -> Vec<VwcRecord> {
+    let start = NaiveDateTime::parse_from_str("2024-07-01 00:00", "%Y-%m-%d %H:%M").unwrap();
+    (0..240)
+        .map(|h| {
+            let (day, hour) = (h / 24 + 1, h % 24);
+            let vwc = match day {
+                1 | 2 | 6 => 0.3,
+                7 if hour < 12 => 0.15,
+                7 => 0.01,
+                8 => 0.08,
+                9 => f64::NAN,
+                _ => 0.05,
+            };
+            VwcRecord {
+                datetime: start + TimeDelta::hours(h),
+                timezone: 0,
+                raw: 2000.0,
+                temp: 20.0,
+                vwc,
+                frozen: false,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_drought_whole_series() {
+    let options = DroughtOptions {
+        period: Period::All,
+        percentiles: vec![0.0, 50.0, 100.0],
+        ..DroughtOptions::default()
+    };
+    let stats = drought_stats(&series(), &options);
+    assert_eq!(stats.len(), 1);
+    let s = &stats[0];
+    assert_eq!(s.days, 9);
+    assert_eq!(s.days_below, 6);
+    assert_close(s.duration_below, 5.5);
+    assert_eq!(s.longest_dry_spell, 3);
+    assert_eq!(s.dry_spell_start, Some(date("2024-07-03")));
+    assert_close(s.percentiles[0], 0.01);
+    assert_close(s.percentiles[1], 0.065);
+    assert_close(s.percentiles[2], 0.3);
+}
+
+#[test]
+fn test_drought_per_period() {
+    let options = DroughtOptions {
+        threshold: 0.06,
+        period: Period::Day,
+        ..DroughtOptions::default()
+    };
+    let stats = drought_stats(&series(), &options);
+    assert_eq!(stats.len(), 10);
+    assert_eq!(stats[2].days_below, 1);
+    assert_eq!(stats[2].longest_dry_spell, 1);
+    // Day 7 is wet on average but dry half of the time
+    assert_eq!(stats[6].days_below, 0);
+    assert_close(stats[6].duration_below, 0.5);
+    // No value on day 9
+    assert_eq!(stats[8].days, 0);
+    assert_eq!(stats[8].dry_spell_start, None);
+    assert!(stats[8].percentiles.iter().all(|p| p.is_nan()));
+
+    // The missing day 9 breaks the spell of days 7, 8 and 10
+    let last_days = drought_stats(
+        &series()[120..],
+        &DroughtOptions {
+            period: Period::All,
+            threshold: 0.09,
+            ..DroughtOptions::default()
+        },
+    );
+    assert_eq!(last_days[0].days_below, 3);
+    assert_eq!(last_days[0].longest_dry_spell, 2);
+    assert_eq!(last_days[0].dry_spell_start, Some(date("2024-07-07")));
+}
@@ -0,0 +1,57 @@
+use soil_sensor_toolbox::{
+    process_ensemble, process_file, read_tms_file, FrozenPolicy, SoilEnsemble, SoilParams,
+    SoilRegistry, SoilType, VwcOptions, TMS4_SOILS,
+};
+
+const FILE: &str = "tests/fixtures/batch/data_94184102_2024_01_11_0.csv";
+
+#[test]
+fn test_ensemble_matches_single_soils() {
+    let records = read_tms_file(FILE).unwrap();
+    let ensemble = SoilEnsemble::tms4();
+    assert_eq!(ensemble.members.len(), TMS4_SOILS.len());
+    let out = process_ensemble(&records, &ensemble, &VwcOptions::default());
+    assert_eq!(out.len(), records.len());
+
+    for (i, soil) in TMS4_SOILS.iter().enumerate() {
+        let single = process_file(FILE.to_string(), *soil).unwrap();
+        for (e, s) in out.iter().zip(&single) {
+            assert_eq!(e.datetime, s.datetime);
+            assert!((e.vwc[i] - s.vwc).abs() < 1e-12);
+        }
+    }
+    for rec in &out {
+        assert!(rec.min <= rec.median && rec.median <= rec.max);
+        assert!((rec.spread - (rec.max - rec.min)).abs() < 1e-12);
+        assert!(rec.vwc.iter().all(|v| *v >= rec.min && *v <= rec.max));
+    }
+    // The curves disagree on a wet soil
+    assert!(out[0].raw > 1900.0);
+    assert!(out[0].spread > 0.05);
+}
+
+#[test]
+fn test_ensemble_from_registry() {
+    let mut registry = SoilRegistry::builtin();
+    registry.insert("Alpine_Loam", SoilParams::new(-1e-8, 2.5e-4, -0.05));
+    let ensemble = SoilEnsemble::from_registry(&registry, &["loam", "Alpine_Loam"]).unwrap();
+    assert_eq!(
+        ensemble.names().collect::<Vec<_>>(),
+        ["loam", "alpine_loam"]
+    );
+    assert_eq!(ensemble.members[0].1, SoilParams::from(SoilType::Loam));
+
+    assert!(SoilEnsemble::from_registry(&registry, &["loam", "clay"]).is_err());
+    assert!(SoilEnsemble::from_registry::<&str>(&registry, &[]).is_err());
+
+    // Frozen records are missing for every member, and in the summary
+    let records = read_tms_file(FILE).unwrap();
+    let options = VwcOptions {
+        frozen: FrozenPolicy::Missing,
+        ..VwcOptions::default()
+    };
+    let out = process_ensemble(&records, &ensemble, &options);
+    let frozen = out.iter().find(|r| r.frozen).unwrap();
+    assert!(frozen.vwc.iter().all(|v| v.is_nan()));
+    assert!(frozen.min.is_nan() && frozen.median.is_nan() && frozen.spread.is_nan());
+}
@@ -0,0 +1,98 @@
+use soil_sensor_toolbox::{
+    process_file, relative_extractable_water, water_potential, HydraulicModel, SoilType,
+    FIELD_CAPACITY_KPA, WILTING_POINT_KPA,
+};
+
+fn assert_close(a: f64, b: f64, tol: f64) {
+    assert!((a - b).abs() < tol, "{a} != {b}");
+}
+
+fn models() -> Vec<HydraulicModel> {
+    SoilType::ALL
+        .iter()
+        .filter(|s| **s != SoilType::Water)
+        .flat_map(|s| {
+            HydraulicModel::van_genuchten(*s)
+                .into_iter()
+                .chain([HydraulicModel::campbell(*s).unwrap()])
+        })
+        .collect()
+}
+
+#[test]
+fn test_loam_retention() {
+    let vg = HydraulicModel::van_genuchten(SoilType::Loam).unwrap();
+    assert_close(vg.field_capacity(), 0.164_446, 1e-6);
+    assert_close(vg.wilting_point(), 0.088_272, 1e-6);
+    assert_close(vg.potential(0.164_446_137), FIELD_CAPACITY_KPA, 1e-3);
+
+    let campbell = HydraulicModel::campbell(SoilType::Loam).unwrap();
+    assert_close(campbell.field_capacity(), 0.313_999, 1e-6);
+    assert_close(campbell.potential(0.3), -42.196_514, 1e-6);
+
+    // The universal curve is treated as a loam
+    assert_eq!(HydraulicModel::van_genuchten(SoilType::Universal), Some(vg));
+    assert_eq!(HydraulicModel::campbell(SoilType::Water), None);
+    // Peat has cited Campbell parameters only
+    assert_eq!(HydraulicModel::van_genuchten(SoilType::Peat), None);
+    assert!(HydraulicModel::campbell(SoilType::Peat).is_some());
+}
+
+#[test]
+fn test_retention_round_trip() {
+    for model in models() {
+        let (fc, wp) = (model.field_capacity(), model.wilting_point());
+        assert!(wp < fc, "{model:?}");
+        for psi in [-1500.0, -100.0, FIELD_CAPACITY_KPA, -10.0] {
+            assert_close(model.potential(model.vwc(psi)), psi, 1e-6 * -psi);
+        }
+        // Drier soils hold the water more strongly
+        assert!(model.potential(wp) < model.potential(fc));
+        assert_close(model.potential(wp), WILTING_POINT_KPA, 1e-6);
+    }
+}
+
+#[test]
+fn test_retention_limits() {
+    let HydraulicModel::VanGenuchten(vg) = HydraulicModel::van_genuchten(SoilType::Sand).unwrap()
+    else {
+        unreachable!()
+    };
+    assert_close(vg.potential(vg.theta_s), 0.0, f64::EPSILON);
+    assert!(vg.potential(vg.theta_r).is_nan());
+    assert!(vg.potential(f64::NAN).is_nan());
+
+    let HydraulicModel::Campbell(campbell) = HydraulicModel::campbell(SoilType::Sand).unwrap()
+    else {
+        unreachable!()
+    };
+    assert_close(campbell.potential(0.5), campbell.psi_e, f64::EPSILON);
+    assert_close(campbell.vwc(0.0), campbell.theta_s, f64::EPSILON);
+    assert!(campbell.potential(0.0).is_nan());
+}
+
+#[test]
+fn test_relative_extractable_water() {
+    assert_close(relative_extractable_water(0.3, 0.3, 0.1), 1.0, f64::EPSILON);
+    assert_close(relative_extractable_water(0.1, 0.3, 0.1), 0.0, f64::EPSILON);
+    assert_close(relative_extractable_water(0.15, 0.3, 0.1), 0.25, 1e-12);
+
+    for model in models() {
+        assert_close(model.rew(model.field_capacity()), 1.0, 1e-9);
+        assert_close(model.rew(model.wilting_point()), 0.0, 1e-9);
+    }
+}
+
+#[test]
+fn test_water_potential_of_vwc() {
+    let records = process_file("tests/fixtures/data/data.csv".to_string(), SoilType::Loam).unwrap();
+    let model = HydraulicModel::van_genuchten(SoilType::Loam).unwrap();
+    let potential = water_potential(&records, &model);
+    assert_eq!(potential.len(), records.len());
+    for (rec, psi) in records.iter().zip(&potential) {
+        if rec.vwc > 0.078 {
+            assert!(*psi <= 0.0);
+            assert_close(model.vwc(*psi), rec.vwc.min(0.43), 1e-9);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use chrono::NaiveDateTime;
+use soil_sensor_toolbox::{
+    climate_indices, mc_calc_fdd, mc_calc_gdd, read_tms_file, IndexOptions, Period, TempSensor,
+};
+
+const FILE: &str = "tests/fixtures/snow/data_94190004_2024_01_21_0.csv";
+
+fn dt(s: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+}
+
+fn assert_close(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-9, "{a} != {b}");
+}
+
+#[test]
+fn test_degree_days() {
+    let records = read_tms_file(FILE).unwrap();
+    let gdd = mc_calc_gdd(&records, TempSensor::T3, 5.0);
+    let fdd = mc_calc_fdd(&records, TempSensor::T3, 0.0);
+    assert_eq!(gdd.len(), records.len());
+    // 15 minute records count for 1/96 day
+    assert_close(gdd[0], 0.0);
+    assert_close(fdd[0], 2.25 / 96.0);
+    assert_close(gdd.iter().sum(), 13.046_875);
+    assert_close(fdd.iter().sum(), 20.3125);
+}
+
+#[test]
+fn test_climate_indices() {
+    let records = read_tms_file(FILE).unwrap();
+    let daily = climate_indices(
+        &records,
+        &IndexOptions {
+            period: Period::Day,
+            ..IndexOptions::default()
+        },
+    );
+    assert_eq!(daily.len(), 20);
+    assert_eq!(daily[0].start, dt("2024-01-01 00:00"));
+    assert_eq!(daily[0].end, dt("2024-01-02 00:00"));
+    assert_close(daily[0].gdd, 0.652_343_75);
+    assert_close(daily[0].fdd, 1.015_625);
+    // The air freezes every night and thaws every day
+    assert!(daily
+        .iter()
+        .all(|d| d.frost_days == 1 && d.freeze_thaw_cycles == 1));
+
+    let monthly = climate_indices(&records, &IndexOptions::default());
+    assert_eq!(monthly.len(), 1);
+    assert_close(monthly[0].gdd, 13.046_875);
+    assert_eq!(monthly[0].frost_days, 20);
+    assert_eq!(monthly[0].freeze_thaw_cycles, 20);
+}
+
+#[test]
+fn test_soil_freeze_thaw() {
+    let records = read_tms_file(FILE).unwrap();
+    // The soil freezes slightly under the two snow covers
+    let soil = IndexOptions {
+        sensor: TempSensor::T1,
+        period: Period::All,
+        ..IndexOptions::default()
+    };
+    let all = climate_indices(&records, &soil);
+    assert_eq!(all.len(), 1);
+    assert_eq!(all[0].frost_days, 9);
+    assert_eq!(all[0].freeze_thaw_cycles, 2);
+    assert_close(all[0].fdd, 2.25);
+
+    // Below the margin around 0 °C
+    let margin = IndexOptions {
+        freeze_thaw_margin: 0.5,
+        ..soil
+    };
+    assert_eq!(climate_indices(&records, &margin)[0].freeze_thaw_cycles, 0);
+    let warm = IndexOptions {
+        frost_threshold: -1.0,
+        fdd_base: -1.0,
+        ..soil
+    };
+    assert_eq!(climate_indices(&records, &warm)[0].frost_days, 0);
+    assert_close(climate_indices(&records, &warm)[0].fdd, 0.0);
+}
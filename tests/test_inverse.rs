@@ -0,0 +1,96 @@
+use soil_sensor_toolbox::{
+    mc_calc_raw, mc_calc_raw_calibrated, mc_calc_vwc, mc_calc_vwc_calibrated, Calibration,
+    SoilParams, SoilType,
+};
+
+const TEMPERATURES: [f64; 6] = [-10.0, 0.0, 10.0, 24.0, 35.0, f64::NAN];
+
+#[test]
+fn test_vwc_raw_round_trip() {
+    for soil in SoilType::ALL {
+        for temp in TEMPERATURES {
+            let mut checked = 0;
+            for i in 0..=100 {
+                let vwc = f64::from(i) / 100.0;
+                let raw = mc_calc_raw(vwc, temp, soil);
+                if raw.is_nan() {
+                    continue;
+                }
+                let back = mc_calc_vwc(raw, temp, soil);
+                assert!(
+                    (back - vwc).abs() < 1e-9,
+                    "{soil:?} at {temp} °C: {vwc} -> {raw} -> {back}"
+                );
+                checked += 1;
+            }
+            // Every curve covers at least the range from 0.2 to 0.5 m³/m³
+            assert!(checked >= 31, "{soil:?} at {temp} °C: {checked}");
+        }
+    }
+}
+
+#[test]
+fn test_raw_vwc_round_trip() {
+    for soil in SoilType::ALL {
+        for temp in TEMPERATURES {
+            for raw in (1000..=3500).step_by(50).map(f64::from) {
+                let vwc = mc_calc_vwc(raw, temp, soil);
+                // Clamped values do not identify the count
+                if vwc <= 0.0 || vwc >= 1.0 {
+                    continue;
+                }
+                let back = mc_calc_raw(vwc, temp, soil);
+                assert!(
+                    (back - raw).abs() < 1e-6,
+                    "{soil:?} at {temp} °C: {raw} -> {vwc} -> {back}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_inverse_edges() {
+    // The curve minimum of peat is about 0.16 m³/m³
+    assert!(mc_calc_raw(0.1, 20.0, SoilType::Peat).is_nan());
+    assert!(!mc_calc_raw(0.2, 20.0, SoilType::Peat).is_nan());
+    // Outside the clamped range
+    assert!(mc_calc_raw(-0.1, 20.0, SoilType::Loam).is_nan());
+    assert!(mc_calc_raw(1.1, 20.0, SoilType::Loam).is_nan());
+    assert!(mc_calc_raw(f64::NAN, 20.0, SoilType::Loam).is_nan());
+
+    // 0 is the driest count before the clamping
+    let raw = mc_calc_raw(0.0, 20.0, SoilType::Universal);
+    assert!(mc_calc_vwc(raw + 1.0, 20.0, SoilType::Universal) > 0.0);
+    assert!(mc_calc_vwc(raw - 1.0, 20.0, SoilType::Universal).abs() < f64::EPSILON);
+
+    // A linear curve without temperature correction
+    let soil = SoilParams {
+        acor_t: 0.0,
+        wcor_t: 0.0,
+        ..SoilParams::new(0.0, 0.0003, -0.1)
+    };
+    assert!((mc_calc_raw(0.5, 5.0, soil) - 2000.0).abs() < 1e-9);
+
+    // A decreasing linear curve, with and without temperature correction
+    let decreasing = SoilParams::new(0.0, -0.0003, 1.0);
+    let uncorrected = SoilParams {
+        acor_t: 0.0,
+        wcor_t: 0.0,
+        ..decreasing
+    };
+    assert!((mc_calc_raw(0.4, 5.0, uncorrected) - 2000.0).abs() < 1e-9);
+    for temp in TEMPERATURES {
+        let raw = mc_calc_raw(0.4, temp, decreasing);
+        assert!(raw.is_finite(), "{temp} °C: {raw}");
+        assert!((mc_calc_vwc(raw, temp, decreasing) - 0.4).abs() < 1e-9);
+    }
+
+    let calibration = Calibration {
+        cor_factor: -150.0,
+        cor_slope: 40.0,
+    };
+    let raw = mc_calc_raw_calibrated(0.3, 12.0, SoilType::Loam, calibration);
+    let back = mc_calc_vwc_calibrated(raw, 12.0, SoilType::Loam, calibration);
+    assert!((back - 0.3).abs() < 1e-9);
+}
@@ -0,0 +1,134 @@
+use chrono::NaiveDateTime;
+use soil_sensor_toolbox::{
+    find_tms_files, merge_records, merge_tms_files, read_tms_file, write_tms, ClockReset,
+    TmsReadOptions, TmsReader,
+};
+
+const DIR: &str = "tests/fixtures/merge";
+const FIRST: &str = "tests/fixtures/merge/data_94190002_2024_05_02_0.csv";
+const SECOND: &str = "tests/fixtures/merge/data_94190002_2024_05_04_0.csv";
+
+fn dt(s: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+}
+
+#[test]
+fn test_merge_tms_files() {
+    let files = find_tms_files(DIR).unwrap();
+    let loggers = merge_tms_files(&files, &TmsReadOptions::default()).unwrap();
+    assert_eq!(loggers.len(), 2);
+
+    let logger = &loggers[0];
+    assert_eq!(logger.serial, "94190002");
+    assert_eq!(logger.files.len(), 2);
+    let report = &logger.report;
+    assert_eq!(report.sources, 2);
+    assert_eq!(report.count_read, 192 + 288);
+    assert_eq!(report.count_duplicates, 191);
+    assert!(!report.is_consistent());
+
+    // The second download disagrees at noon of the first day
+    assert_eq!(report.conflicts.len(), 1);
+    let conflict = report.conflicts[0];
+    assert_eq!(conflict.kept.datetime, dt("2024-05-01 12:00"));
+    assert_eq!((conflict.kept_source, conflict.dropped_source), (0, 1));
+    assert!((conflict.kept.moisture - 2006.0).abs() < f64::EPSILON);
+    assert!((conflict.dropped.moisture - 2100.0).abs() < f64::EPSILON);
+
+    // ... and its clock was reset after 2024-05-03 12:00
+    assert_eq!(
+        report.clock_resets,
+        [ClockReset {
+            source: 1,
+            before: dt("2024-05-03 12:00"),
+            after: dt("2000-01-01 00:00"),
+            set_aside: 0,
+        }]
+    );
+
+    // Continuous series in time order, records after the reset first
+    assert_eq!(logger.records.len(), 288);
+    assert!(logger
+        .records
+        .windows(2)
+        .all(|w| w[0].datetime < w[1].datetime));
+    assert_eq!(logger.records[46].datetime, dt("2000-01-01 11:30"));
+    assert_eq!(logger.records[47].datetime, dt("2024-05-01 00:00"));
+    assert_eq!(
+        logger.records.last().unwrap().datetime,
+        dt("2024-05-03 12:00")
+    );
+
+    // Single download
+    assert_eq!(loggers[1].serial, "94190003");
+    assert!(loggers[1].report.is_consistent());
+    assert_eq!(loggers[1].records.len(), 96);
+}
+
+#[test]
+fn test_merge_order() {
+    let first = read_tms_file(FIRST).unwrap();
+    let second = read_tms_file(SECOND).unwrap();
+    let (records, report) = merge_records(&[second.clone(), first.clone()]);
+    assert_eq!(records.len(), 288);
+    assert_eq!(report.conflicts.len(), 1);
+    // The first download given wins
+    let noon = records
+        .iter()
+        .find(|r| r.datetime == dt("2024-05-01 12:00"))
+        .unwrap();
+    assert!((noon.moisture - 2100.0).abs() < f64::EPSILON);
+
+    let (records, report) = merge_records(&[first.clone(), first.clone()]);
+    assert_eq!(records.len(), first.len());
+    assert_eq!(report.count_duplicates, first.len());
+    assert!(report.is_consistent());
+}
+
+#[test]
+fn test_clock_reset_between_downloads() {
+    // Memory cleared and clock set back to the start of the first download
+    // before the second one, which holds other readings for the same times
+    let first = read_tms_file(FIRST).unwrap();
+    let mut reset = first[..96].to_vec();
+    for rec in &mut reset {
+        rec.moisture += 300.0;
+    }
+    let (records, report) = merge_records(&[first.clone(), reset.clone()]);
+    assert_eq!(records, first);
+    assert!(report.conflicts.is_empty());
+    assert_eq!(
+        report.clock_resets,
+        [ClockReset {
+            source: 1,
+            before: first.last().unwrap().datetime,
+            after: dt("2024-05-01 00:00"),
+            set_aside: 96,
+        }]
+    );
+    assert_eq!(report.set_aside, [(1, reset.clone())]);
+    assert!(!report.is_consistent());
+
+    // A few differing readings are conflicts, not a reset
+    let mut differing = first[..96].to_vec();
+    differing[10].moisture += 300.0;
+    let (_, report) = merge_records(&[first.clone(), differing]);
+    assert_eq!(report.conflicts.len(), 1);
+    assert!(report.clock_resets.is_empty() && report.set_aside.is_empty());
+}
+
+#[test]
+fn test_write_tms() {
+    let mut records = read_tms_file(FIRST).unwrap();
+    records[3].t2 = f64::NAN;
+    let mut buf = Vec::new();
+    write_tms(&mut buf, &records).unwrap();
+    assert!(String::from_utf8_lossy(&buf).starts_with("0;2024.05.01 00:00;8;"));
+
+    let read: Vec<_> = TmsReader::new(buf.as_slice())
+        .collect::<anyhow::Result<_>>()
+        .unwrap();
+    assert_eq!(read.len(), records.len());
+    assert!(read[3].t2.is_nan());
+    assert_eq!(read[10], records[10]);
+}
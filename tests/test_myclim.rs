@@ -1,4 +1,7 @@
-use soil_sensor_toolbox::{process_file, SoilType};
+use soil_sensor_toolbox::{
+    process_file, process_file_with_options, CalibrationTable, FrozenPolicy, SoilType, VwcOptions,
+    VwcRecord,
+};
 use std::error::Error;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
@@ -35,12 +38,19 @@ fn get_expected_filename(soil_type: SoilType) -> String {
     format!("tests/fixtures/data/output_{soil_name}.csv")
 }
 
-#[allow(clippy::too_many_lines)]
 fn compare_soil_type(soil_type: SoilType) -> Result<(), Box<dyn Error>> {
     let actual_data = process_file("tests/fixtures/data/data.csv".to_string(), soil_type)?;
     let expected_file = get_expected_filename(soil_type);
+    compare_with_expected(&actual_data, &expected_file, &format!("{soil_type:?}"))
+}
 
-    let file = File::open(&expected_file)
+#[allow(clippy::too_many_lines)]
+fn compare_with_expected(
+    actual_data: &[VwcRecord],
+    expected_file: &str,
+    label: &str,
+) -> Result<(), Box<dyn Error>> {
+    let file = File::open(expected_file)
         .map_err(|e| format!("Failed to open expected file {expected_file}: {e}"))?;
     let reader = BufReader::new(file);
     let expected_lines: Vec<String> = reader.lines().collect::<Result<Vec<_>, _>>()?;
@@ -51,7 +61,7 @@ fn compare_soil_type(soil_type: SoilType) -> Result<(), Box<dyn Error>> {
     assert_eq!(
         actual_data.len(),
         expected_data_lines.len(),
-        "Data length mismatch for {soil_type:?}: actual={}, expected={}",
+        "Data length mismatch for {label}: actual={}, expected={}",
         actual_data.len(),
         expected_data_lines.len()
     );
@@ -69,9 +79,13 @@ fn compare_soil_type(soil_type: SoilType) -> Result<(), Box<dyn Error>> {
             let expected_datetime = parts[0].trim_matches('"');
             let expected_raw: f64 = parts[1].parse()?;
             let expected_temp: f64 = parts[2].parse()?;
-            let expected_vwc: f64 = parts[3].parse()?;
+            // myClim writes NA for frozen soil with frozen2NA = TRUE
+            let expected_vwc: f64 = match parts[3] {
+                "NA" => f64::NAN,
+                v => v.parse()?,
+            };
 
-            let actual_datetime = actual.0.format("%Y-%m-%d %H:%M:%S").to_string();
+            let actual_datetime = actual.datetime.format("%Y-%m-%d %H:%M:%S").to_string();
 
             // Check datetime
             if actual_datetime != expected_datetime {
@@ -85,37 +99,49 @@ fn compare_soil_type(soil_type: SoilType) -> Result<(), Box<dyn Error>> {
             }
 
             // Check raw value with small tolerance for float comparison
-            if (actual.1 - expected_raw).abs() > FLOAT_TOLERANCE {
+            if (actual.raw - expected_raw).abs() > FLOAT_TOLERANCE {
                 mismatches.push(Mismatch {
                     index: i,
                     field: "raw".to_string(),
                     expected: expected_raw.to_string(),
-                    actual: actual.1.to_string(),
-                    diff: Some((actual.1 - expected_raw).abs()),
+                    actual: actual.raw.to_string(),
+                    diff: Some((actual.raw - expected_raw).abs()),
                 });
             }
 
             // Check temperature with small tolerance for float comparison
-            if (actual.2 - expected_temp).abs() > FLOAT_TOLERANCE {
+            if (actual.temp - expected_temp).abs() > FLOAT_TOLERANCE {
                 mismatches.push(Mismatch {
                     index: i,
                     field: "temperature".to_string(),
                     expected: expected_temp.to_string(),
-                    actual: actual.2.to_string(),
-                    diff: Some((actual.2 - expected_temp).abs()),
+                    actual: actual.temp.to_string(),
+                    diff: Some((actual.temp - expected_temp).abs()),
                 });
             }
 
-            // Check VWC with tolerance
-            let vwc_diff = (actual.3 - expected_vwc).abs();
-            if vwc_diff > VWC_TOLERANCE {
-                mismatches.push(Mismatch {
-                    index: i,
-                    field: "VWC".to_string(),
-                    expected: expected_vwc.to_string(),
-                    actual: actual.3.to_string(),
-                    diff: Some(vwc_diff),
-                });
+            // Check VWC with tolerance, missing values must match exactly
+            if expected_vwc.is_nan() || actual.vwc.is_nan() {
+                if expected_vwc.is_nan() != actual.vwc.is_nan() {
+                    mismatches.push(Mismatch {
+                        index: i,
+                        field: "VWC missing".to_string(),
+                        expected: parts[3].to_string(),
+                        actual: actual.vwc.to_string(),
+                        diff: None,
+                    });
+                }
+            } else {
+                let vwc_diff = (actual.vwc - expected_vwc).abs();
+                if vwc_diff > VWC_TOLERANCE {
+                    mismatches.push(Mismatch {
+                        index: i,
+                        field: "VWC".to_string(),
+                        expected: expected_vwc.to_string(),
+                        actual: actual.vwc.to_string(),
+                        diff: Some(vwc_diff),
+                    });
+                }
             }
         }
     }
@@ -125,7 +151,7 @@ fn compare_soil_type(soil_type: SoilType) -> Result<(), Box<dyn Error>> {
     #[allow(clippy::cast_precision_loss)]
     let mismatch_percentage = (mismatch_count as f64 / total_records as f64) * 100.0;
 
-    println!("\n=== Comparison Results for {soil_type:?} ===");
+    println!("\n=== Comparison Results for {label} ===");
     println!("Total records: {total_records}");
     println!("Mismatches: {mismatch_count} ({mismatch_percentage:.2}%)");
 
@@ -207,8 +233,8 @@ fn compare_soil_type(soil_type: SoilType) -> Result<(), Box<dyn Error>> {
 
     if !critical_mismatches.is_empty() {
         return Err(format!(
-            "Critical mismatches found for {:?}: {} out of {} total mismatches",
-            soil_type,
+            "Critical mismatches found for {}: {} out of {} total mismatches",
+            label,
             critical_mismatches.len(),
             mismatch_count
         )
@@ -227,6 +253,73 @@ fn compare_soil_type(soil_type: SoilType) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// VWC with the field calibration from `calibration.csv` applied
+/// (myClim `mc_prep_calib_load`), universal soil type
+#[test]
+fn test_calibrated_universal() {
+    let table = CalibrationTable::from_path("tests/fixtures/data/calibration.csv")
+        .expect("Failed to read calibration table");
+    let options = VwcOptions {
+        calibration: table.schedule("data"),
+        ..VwcOptions::default()
+    };
+    let actual_data = process_file_with_options(
+        "tests/fixtures/data/data.csv".to_string(),
+        SoilType::Universal,
+        &options,
+    )
+    .expect("Failed to process data file");
+    // Pinned values, one record per calibration period: uncalibrated (in
+    // air), cor_factor 120, cor_factor -80 with cor_slope 250, and reset
+    // to 0. Worked out by hand from the myClim mc_calc_vwc equations, not
+    // from a myClim run; script_all_soils.R prints the same rows.
+    for (i, expected) in [
+        (1557, 0.0),
+        (10389, 0.541_430),
+        (19125, 0.552_198),
+        (33717, 0.544_483),
+    ] {
+        let vwc = actual_data[i].vwc;
+        assert!(
+            (vwc - expected).abs() < 1e-6,
+            "record {i}: {vwc} != {expected}"
+        );
+    }
+    compare_with_expected(
+        &actual_data,
+        "tests/fixtures/data/output_calibrated_universal.csv",
+        "calibrated Universal",
+    )
+    .expect("Calibrated universal test failed");
+}
+
+/// Winter data with VWC of frozen soil set to NA (myClim `frozen2NA = TRUE`)
+#[test]
+fn test_frozen_to_na_universal() {
+    let options = VwcOptions {
+        frozen: FrozenPolicy::Missing,
+        ..VwcOptions::default()
+    };
+    let actual_data = process_file_with_options(
+        "tests/fixtures/data/data_winter.csv".to_string(),
+        SoilType::Universal,
+        &options,
+    )
+    .expect("Failed to process winter data file");
+    assert!(actual_data.iter().any(|r| r.frozen && r.vwc.is_nan()));
+    assert!(actual_data.iter().all(|r| r.frozen == r.vwc.is_nan()));
+    // Pinned values worked out by hand from the myClim equations, see
+    // test_calibrated_universal
+    assert!(actual_data[0].vwc.is_nan() && actual_data[200].vwc.is_nan());
+    assert!((actual_data[40].vwc - 0.313_487).abs() < 1e-6);
+    compare_with_expected(
+        &actual_data,
+        "tests/fixtures/data/output_frozen_universal.csv",
+        "frozen2NA Universal",
+    )
+    .expect("frozen2NA universal test failed");
+}
+
 // Individual tests for each soil type
 #[test]
 fn test_sand() {
@@ -0,0 +1,63 @@
+use chrono::NaiveDateTime;
+use soil_sensor_toolbox::{qc_flags, read_tms_file, QcFlags, QcOptions};
+
+const FILE: &str = "tests/fixtures/deployment/data_94190001_2024_05_09_0.csv";
+
+fn dt(s: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+}
+
+#[test]
+fn test_qc_out_of_soil() {
+    let records = read_tms_file(FILE).unwrap();
+    let flags = qc_flags(&records, &QcOptions::default());
+    assert_eq!(flags.len(), records.len());
+    let at = |s: &str| flags[records.iter().position(|r| r.datetime == dt(s)).unwrap()];
+
+    // Full day in air: air counts and T1 following the air temperature
+    let exposed = at("2024-05-01 12:00");
+    assert!(exposed.air && exposed.exposure && !exposed.step);
+    // Installation and removal are steps of the count
+    assert!(at("2024-05-02 10:00").step);
+    assert!(at("2024-05-07 16:00").step && at("2024-05-07 16:00").air);
+    assert!(at("2024-05-08 12:00").exposure);
+
+    // Nothing to report in the soil
+    assert!(records
+        .iter()
+        .zip(&flags)
+        .filter(|(r, _)| r.datetime > dt("2024-05-02 10:00") && r.datetime < dt("2024-05-07 16:00"))
+        .all(|(_, f)| f.is_ok()));
+}
+
+#[test]
+fn test_qc_range_and_step() {
+    let mut records = read_tms_file(FILE).unwrap()[200..210].to_vec();
+    records[3].moisture = 4095.0;
+    records[5].moisture = f64::NAN;
+    let flags = qc_flags(&records, &QcOptions::default());
+    assert!(flags[3].out_of_range && flags[3].step);
+    // Back to the previous level
+    assert!(flags[4].step && !flags[4].out_of_range);
+    // Missing count raises no flag, the step is assessed across it
+    assert!(flags[5].is_ok());
+    assert!(flags[6].is_ok());
+
+    let loose = QcOptions {
+        raw_max: 4100.0,
+        max_step: 5000.0,
+        ..QcOptions::default()
+    };
+    assert!(qc_flags(&records, &loose).iter().all(QcFlags::is_ok));
+}
+
+#[test]
+fn test_qc_codes() {
+    assert_eq!(QcFlags::default().codes(), "ok");
+    let flags = QcFlags {
+        air: true,
+        exposure: true,
+        ..QcFlags::default()
+    };
+    assert_eq!(flags.codes(), "air|exposure");
+}
@@ -0,0 +1,124 @@
+use chrono::{NaiveDateTime, TimeDelta};
+use soil_sensor_toolbox::{
+    resample, Interpolation, ResampleFlag, ResampleOptions, TmsRecord, VwcRecord,
+};
+
+fn datetime(s: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+}
+
+fn assert_close(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-9, "{a} != {b}");
+}
+
+fn record(s: &str, index: u64, value: f64) -> TmsRecord {
+    TmsRecord {
+        index: Some(index),
+        datetime: datetime(s),
+        timezone: 4,
+        t1: value,
+        t2: value,
+        t3: value,
+        moisture: 1000.0 + value * 100.0,
+        shake: 0,
+        err_flag: 0,
+    }
+}
+
+/// Logging every 15 minutes, reconfigured to every 10 minutes after a
+/// 2 hour gap.
+fn series() -> Vec<TmsRecord> {
+    vec![
+        record("2024-05-01 00:00", 0, 0.0),
+        record("2024-05-01 00:15", 1, 1.5),
+        record("2024-05-01 00:30", 2, 3.0),
+        record("2024-05-01 02:30", 3, 15.0),
+        record("2024-05-01 02:40", 4, 16.0),
+        record("2024-05-01 02:50", 5, 17.0),
+    ]
+}
+
+fn flags_of(s: &str) -> Vec<ResampleFlag> {
+    s.chars()
+        .map(|c| match c {
+            'o' => ResampleFlag::Original,
+            'i' => ResampleFlag::Interpolated,
+            _ => ResampleFlag::Missing,
+        })
+        .collect()
+}
+
+#[test]
+fn test_resample_linear() {
+    let options = ResampleOptions {
+        step: TimeDelta::minutes(10),
+        ..ResampleOptions::default()
+    };
+    let (records, flags) = resample(&series(), &options).unwrap();
+
+    // 00:00 to 02:50 every 10 minutes; 00:40 to 02:20 fall in the 2 hour gap
+    assert_eq!(records.len(), 18);
+    assert_eq!(flags, flags_of("oiiommmmmmmmmmmooo"));
+    assert_eq!(records[1].datetime, datetime("2024-05-01 00:10"));
+    assert_close(records[1].t1, 1.0);
+    assert_close(records[2].t1, 2.0);
+    assert_close(records[2].moisture, 1200.0);
+    // Only original records keep their logger index
+    assert_eq!(records[1].index, None);
+    assert!(records[4].t1.is_nan());
+    assert_eq!(records[4].index, None);
+    assert_eq!(records[16].index, Some(4));
+}
+
+#[test]
+fn test_resample_max_gap_and_methods() {
+    let options = ResampleOptions {
+        step: TimeDelta::minutes(10),
+        interpolation: Interpolation::Nearest,
+        max_gap: TimeDelta::hours(2),
+    };
+    let (records, flags) = resample(&series(), &options).unwrap();
+    assert_eq!(flags, flags_of("oiioiiiiiiiiiiiooo"));
+    assert_close(records[1].t1, 1.5);
+    // 00:40 is nearer to 00:30, 01:30 is a tie taking the earlier record
+    assert_close(records[4].t1, 3.0);
+    assert_close(records[9].t1, 3.0);
+    assert_close(records[10].t1, 15.0);
+    assert_eq!(records[4].index, None);
+    assert_eq!(records[3].index, Some(2));
+
+    let options = ResampleOptions {
+        interpolation: Interpolation::None,
+        ..options
+    };
+    let (_, flags) = resample(&series(), &options).unwrap();
+    assert_eq!(flags, flags_of("ommommmmmmmmmmmooo"));
+}
+
+#[test]
+fn test_resample_grid_alignment() {
+    // The grid starts at the first multiple of the step since midnight
+    let records: Vec<VwcRecord> = (0..4i32)
+        .map(|i| VwcRecord {
+            datetime: datetime("2024-05-01 00:07") + TimeDelta::minutes(15 * i64::from(i)),
+            timezone: 0,
+            raw: 2000.0,
+            temp: 10.0,
+            vwc: 0.1 * f64::from(i),
+            frozen: false,
+        })
+        .collect();
+    let (resampled, flags) = resample(&records, &ResampleOptions::default()).unwrap();
+    assert_eq!(resampled.len(), 3);
+    assert_eq!(resampled[0].datetime, datetime("2024-05-01 00:15"));
+    assert_eq!(flags, flags_of("iii"));
+    assert_close(resampled[0].vwc, 0.1 * 8.0 / 15.0);
+
+    let unordered = [records[1], records[0]];
+    assert!(resample(&unordered, &ResampleOptions::default()).is_err());
+    let options = ResampleOptions {
+        step: TimeDelta::zero(),
+        ..ResampleOptions::default()
+    };
+    assert!(resample(&records, &options).is_err());
+}
@@ -0,0 +1,225 @@
+use chrono::NaiveDateTime;
+use soil_sensor_toolbox::{
+    parse_utc_offset, process_file, process_file_with_options, process_sensor_file,
+    read_sensor_csv, roth_organic, topp, Calibration, CalibrationSchedule, Decagon5tm,
+    FrozenPolicy, MoistureSensor, SensorFamily, SensorReading, Smt100, SoilType, Substrate,
+    TempSensor, Teros10, Teros11, TmsReadOptions, VwcOptions, VwcParams,
+};
+use std::fs::File;
+
+const TEROS11: &str = "tests/fixtures/sensor/teros11.csv";
+const ZENTRA: &str = "tests/fixtures/sensor/zentra_z6.csv";
+const SMT100: &str = "tests/fixtures/sensor/smt100.csv";
+const NONE: Calibration = Calibration {
+    cor_factor: 0.0,
+    cor_slope: 0.0,
+};
+
+fn assert_close(a: f64, b: f64, tolerance: f64) {
+    assert!((a - b).abs() < tolerance, "{a} != {b}");
+}
+
+#[test]
+fn test_calibration_equations() {
+    assert_close(topp(20.0), 0.3454, 1e-9);
+    assert_close(roth_organic(20.0), 0.39862, 1e-9);
+
+    let mineral = Teros11::default();
+    assert_close(mineral.vwc(2320.0, f64::NAN, NONE), 0.204_328, 1e-9);
+    assert!(mineral.vwc(1500.0, 20.0, NONE).abs() < f64::EPSILON);
+    let organic = SensorFamily::Teros11.sensor(Substrate::Organic).unwrap();
+    assert_eq!(organic.name(), "teros11");
+    assert!(organic.vwc(2320.0, 20.0, NONE) > mineral.vwc(2320.0, 20.0, NONE));
+
+    // Permittivity output
+    let smt100 = Smt100 {
+        substrate: Substrate::Organic,
+        port: None,
+    };
+    assert_close(smt100.vwc(20.0, 20.0, NONE), roth_organic(20.0), 1e-12);
+    let fivetm = SensorFamily::Decagon5tm.sensor(Substrate::Mineral).unwrap();
+    assert_close(fivetm.vwc(20.0, 20.0, NONE), topp(20.0), 1e-12);
+    assert!(fivetm.vwc(100.0, 20.0, NONE) <= 1.0);
+
+    for family in SensorFamily::ALL {
+        assert_eq!(SensorFamily::try_from(family.as_str()), Ok(family));
+        match family.sensor(Substrate::Mineral) {
+            Ok(sensor) => assert_eq!(sensor.name(), family.as_str()),
+            Err(_) => assert_eq!(family, SensorFamily::Tms),
+        }
+    }
+    assert_eq!(SensorFamily::try_from("TEROS12"), Ok(SensorFamily::Teros11));
+    assert!(SensorFamily::try_from("ec5").is_err());
+    assert_eq!(Substrate::try_from("soilless"), Ok(Substrate::Organic));
+    assert!(Substrate::try_from("loam").is_err());
+}
+
+#[test]
+fn test_read_sensor_csv() {
+    let readings = read_sensor_csv(File::open(TEROS11).unwrap()).unwrap();
+    assert_eq!(readings.len(), 5);
+    let first = NaiveDateTime::parse_from_str("2024-05-14 10:00", "%Y-%m-%d %H:%M").unwrap();
+    assert_eq!(readings[0].datetime, first);
+    assert_close(readings[0].raw, 2320.0, 1e-12);
+    assert_close(readings[0].temp, 12.4, 1e-12);
+    assert!(readings[2].raw.is_nan() && readings[2].temp.is_nan());
+
+    // Semicolons, ISO datetimes and no temperature
+    let data = "datetime;permittivity\n2024-05-14 10:00;18.5\n2024-05-14 10:15;NA\n";
+    let readings = read_sensor_csv(data.as_bytes()).unwrap();
+    assert_eq!(readings.len(), 2);
+    assert_close(readings[0].raw, 18.5, 1e-12);
+    assert!(readings[0].temp.is_nan() && readings[1].raw.is_nan());
+
+    assert!(read_sensor_csv("2024-05-14 10:00,wet\n".as_bytes()).is_err());
+}
+
+fn read(sensor: &dyn MoistureSensor, path: &str) -> anyhow::Result<Vec<SensorReading>> {
+    sensor.read(&mut File::open(path).unwrap(), &TmsReadOptions::default())
+}
+
+#[test]
+fn test_read_zentra_teros10() {
+    // Port 1, the mV column rather than the logger battery voltage
+    let readings = read(&Teros10::default(), ZENTRA).unwrap();
+    assert_eq!(readings.len(), 5);
+    let first = NaiveDateTime::parse_from_str("2024-05-14 10:00", "%Y-%m-%d %H:%M").unwrap();
+    assert_eq!(readings[0].datetime, first);
+    assert_close(readings[0].raw, 1500.0, 1e-12);
+    assert!(readings[0].temp.is_nan());
+    assert!(readings[2].raw.is_nan());
+    assert_close(readings[4].raw, 1490.0, 1e-12);
+
+    let port2 = Teros10 {
+        port: Some(2),
+        ..Teros10::default()
+    };
+    let err = read(&port2, ZENTRA).unwrap_err();
+    assert!(err.to_string().contains("on port 2"), "{err}");
+}
+
+#[test]
+fn test_read_zentra_teros11() {
+    // Port 2, raw counts and soil temperature
+    let readings = read(&Teros11::default(), ZENTRA).unwrap();
+    let reduced = read_sensor_csv(File::open(TEROS11).unwrap()).unwrap();
+    assert_eq!(readings.len(), reduced.len());
+    for (r, e) in readings.iter().zip(&reduced) {
+        assert_eq!(r.datetime, e.datetime);
+        assert!(r.raw.to_bits() == e.raw.to_bits() && r.temp.to_bits() == e.temp.to_bits());
+    }
+
+    let sensor = SensorFamily::Teros11
+        .sensor_on_port(Substrate::Mineral, Some(2))
+        .unwrap();
+    let records = process_sensor_file(ZENTRA, sensor.as_ref(), &VwcOptions::default()).unwrap();
+    assert_close(records[0].vwc, 0.204_328, 1e-9);
+    assert!(records[4].frozen);
+
+    let port3 = Teros11 {
+        port: Some(3),
+        ..Teros11::default()
+    };
+    assert!(read(&port3, ZENTRA).is_err());
+}
+
+#[test]
+fn test_read_zentra_5tm() {
+    // Port 3, permittivity and soil temperature
+    let readings = read(&Decagon5tm::default(), ZENTRA).unwrap();
+    assert_eq!(readings.len(), 5);
+    assert_close(readings[0].raw, 20.0, 1e-12);
+    assert_close(readings[0].temp, 12.1, 1e-12);
+    assert!(readings[2].raw.is_nan() && readings[2].temp.is_nan());
+    assert_close(readings[4].soil_temp, -0.2, 1e-12);
+
+    let sensor = Decagon5tm {
+        port: Some(3),
+        ..Decagon5tm::default()
+    };
+    let records = process_sensor_file(ZENTRA, &sensor, &VwcOptions::default()).unwrap();
+    assert_close(records[0].vwc, topp(20.0), 1e-12);
+}
+
+#[test]
+fn test_read_smt100() {
+    // Separate date and time columns and decimal commas, probe 1 by default
+    let readings = read(&Smt100::default(), SMT100).unwrap();
+    assert_eq!(readings.len(), 5);
+    let first = NaiveDateTime::parse_from_str("2024-05-14 10:00", "%Y-%m-%d %H:%M").unwrap();
+    assert_eq!(readings[0].datetime, first);
+    assert_close(readings[0].raw, 18.52, 1e-12);
+    assert_close(readings[0].temp, 12.4, 1e-12);
+    assert!(readings[2].raw.is_nan() && readings[2].temp.is_nan());
+    assert_close(readings[4].temp, -0.4, 1e-12);
+
+    let probe2 = Smt100 {
+        port: Some(2),
+        ..Smt100::default()
+    };
+    let readings = read(&probe2, SMT100).unwrap();
+    assert_close(readings[2].raw, 24.25, 1e-12);
+    assert_close(readings[4].temp, 0.2, 1e-12);
+
+    let records = process_sensor_file(SMT100, &probe2, &VwcOptions::default()).unwrap();
+    assert_close(records[0].vwc, topp(24.1), 1e-12);
+    let probe3 = Smt100 {
+        port: Some(3),
+        ..Smt100::default()
+    };
+    assert!(read(&probe3, SMT100).is_err());
+}
+
+#[test]
+fn test_process_sensor_file() {
+    let options = VwcOptions {
+        frozen: FrozenPolicy::Missing,
+        ..VwcOptions::default()
+    };
+    let sensor = SensorFamily::Teros11.sensor(Substrate::Mineral).unwrap();
+    let records = process_sensor_file(TEROS11, sensor.as_ref(), &options).unwrap();
+    assert_eq!(records.len(), 5);
+    assert_close(records[0].vwc, 0.204_328, 1e-9);
+    assert!(records[2].vwc.is_nan() && !records[2].frozen);
+    assert!(records[4].frozen && records[4].vwc.is_nan());
+}
+
+#[test]
+fn test_tms_sensor_matches_process_file() {
+    let file = "tests/fixtures/batch/data_94184102_2024_01_11_0.csv";
+    let sensor = VwcParams::from(SoilType::Loam);
+    let records = process_sensor_file(file, &sensor, &VwcOptions::default()).unwrap();
+    let expected = process_file(file.to_string(), SoilType::Loam).unwrap();
+    assert_eq!(records, expected);
+
+    // Air temperature correction, a calibration, frozen soil from T1 and
+    // converted datetimes
+    let sensor = VwcParams {
+        temp_sensor: TempSensor::T3,
+        ..VwcParams::from(SoilType::Loam)
+    };
+    let options = VwcOptions {
+        calibration: CalibrationSchedule::constant(Calibration {
+            cor_factor: 120.0,
+            cor_slope: -80.0,
+        }),
+        frozen: FrozenPolicy::Missing,
+        read: TmsReadOptions {
+            output_tz: Some(parse_utc_offset("+02:00").unwrap()),
+            ..TmsReadOptions::default()
+        },
+        ..VwcOptions::default()
+    };
+    let records = process_sensor_file(file, &sensor, &options).unwrap();
+    let expected = process_file_with_options(file.to_string(), sensor, &options).unwrap();
+    assert_eq!(records.len(), expected.len());
+    for (r, e) in records.iter().zip(&expected) {
+        assert_eq!(
+            (r.datetime, r.timezone, r.frozen),
+            (e.datetime, e.timezone, e.frozen)
+        );
+        assert!(r.temp.to_bits() == e.temp.to_bits() && r.vwc.to_bits() == e.vwc.to_bits());
+    }
+    // Frozen soil is detected from T1, not from a freezing T3
+    assert!(records.iter().any(|r| !r.frozen && r.temp < 0.0));
+}
@@ -0,0 +1,68 @@
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+use soil_sensor_toolbox::{mc_calc_snow, read_tms_file, snow_summary, SnowOptions, TempSensor};
+
+const FILE: &str = "tests/fixtures/snow/data_94190004_2024_01_21_0.csv";
+
+fn dt(s: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+}
+
+fn date(s: &str) -> NaiveDate {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+}
+
+#[test]
+fn test_calc_snow() {
+    let records = read_tms_file(FILE).unwrap();
+    let snow = mc_calc_snow(&records, &SnowOptions::default());
+    assert_eq!(snow.len(), records.len());
+    let at = |s: &str| snow.iter().find(|r| r.datetime == dt(s)).unwrap().snow;
+
+    assert_eq!(at("2024-01-05 23:45"), Some(false));
+    assert_eq!(at("2024-01-06 00:00"), Some(true));
+    assert_eq!(at("2024-01-12 23:45"), Some(true));
+    assert_eq!(at("2024-01-13 00:00"), Some(false));
+    // Two days of snow are shorter than the window
+    assert_eq!(at("2024-01-15 12:00"), Some(false));
+    // Missing T2 in both windows
+    assert_eq!(at("2024-01-19 12:00"), None);
+    assert_eq!(at("2024-01-19 11:45"), Some(false));
+
+    // The air sensor above the snow keeps its diurnal range
+    let air = SnowOptions {
+        sensor: TempSensor::T3,
+        ..SnowOptions::default()
+    };
+    assert!(mc_calc_snow(&records, &air)
+        .iter()
+        .all(|r| r.snow != Some(true)));
+
+    // With a 4 day window (384 records), the 94 records in the middle of the
+    // 672 records of snow have no window within the snow cover
+    let long = SnowOptions {
+        window: TimeDelta::days(4),
+        ..SnowOptions::default()
+    };
+    let snow = mc_calc_snow(&records, &long);
+    assert_eq!(
+        snow.iter().filter(|r| r.snow == Some(true)).count(),
+        672 - 94
+    );
+}
+
+#[test]
+fn test_snow_summary() {
+    let records = read_tms_file(FILE).unwrap();
+    let summary = snow_summary(&mc_calc_snow(&records, &SnowOptions::default()));
+    assert!((summary.snow_days - 7.0).abs() < 1e-9);
+    assert_eq!(summary.first_day, Some(date("2024-01-06")));
+    assert_eq!(summary.last_day, Some(date("2024-01-12")));
+    assert!((summary.longest_period - 7.0).abs() < 1e-9);
+    assert_eq!(summary.longest_start, Some(dt("2024-01-06 00:00")));
+    assert_eq!(summary.longest_end, Some(dt("2024-01-12 23:45")));
+
+    let none = snow_summary(&mc_calc_snow(&records[..300], &SnowOptions::default()));
+    assert!(none.snow_days.abs() < f64::EPSILON);
+    assert_eq!(none.first_day, None);
+    assert_eq!(none.longest_start, None);
+}
@@ -0,0 +1,134 @@
+use soil_sensor_toolbox::{
+    mc_calc_vwc, process_file, process_records, read_tms_file, SoilParams, SoilRegistry, SoilType,
+    TempSensor, VwcOptions, VwcParams, ACOR_T, REF_T, WCOR_T,
+};
+
+#[test]
+fn test_builtin_registry() {
+    let registry = SoilRegistry::builtin();
+    assert_eq!(registry.names().count(), SoilType::ALL.len());
+    for soil in SoilType::ALL {
+        assert_eq!(registry.get(soil.as_str()), Some(SoilParams::from(soil)));
+    }
+    assert_eq!(
+        registry.get("Universal"),
+        Some(SoilParams::from(SoilType::Universal))
+    );
+    assert!(registry.resolve("alpine_loam").is_err());
+}
+
+#[test]
+fn test_custom_params_match_builtin() {
+    let custom = SoilParams::new(-1.34e-08, 0.000_249_622, -0.157_888_8);
+    for (raw, temp) in [(1500.0, 12.5), (2800.0, -3.0), (900.0, f64::NAN)] {
+        let expected = mc_calc_vwc(raw, temp, SoilType::Universal);
+        assert!((mc_calc_vwc(raw, temp, custom) - expected).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn test_extend_from_csv() {
+    let mut registry = SoilRegistry::builtin();
+    registry
+        .extend_from_path("tests/fixtures/soil/soils.csv")
+        .unwrap();
+
+    let alpine = registry.resolve("alpine_loam").unwrap();
+    assert_eq!(alpine, SoilParams::new(-4.2e-8, 0.000_35, -0.25));
+
+    let peat = registry.get("warm_peat").unwrap();
+    let defaults = SoilParams::from(SoilType::Peat);
+    assert!((peat.ref_t - 20.0).abs() < f64::EPSILON);
+    assert!((peat.acor_t - defaults.acor_t).abs() < f64::EPSILON);
+    assert!((peat.wcor_t - defaults.wcor_t).abs() < f64::EPSILON);
+
+    // A different reference temperature changes the correction
+    let peat_24 = SoilParams {
+        ref_t: defaults.ref_t,
+        ..peat
+    };
+    assert!((mc_calc_vwc(2000.0, 10.0, peat) - mc_calc_vwc(2000.0, 10.0, peat_24)).abs() > 1e-4);
+
+    // Builtin soils are kept
+    assert!(registry.get("loam").is_some());
+
+    let unnamed = "a,b,c\n-4.2e-8,0.00035,-0.25\n";
+    assert!(registry.extend_from_csv(unnamed.as_bytes()).is_err());
+}
+
+#[test]
+fn test_extend_from_toml() {
+    let mut from_toml = SoilRegistry::builtin();
+    from_toml
+        .extend_from_path("tests/fixtures/soil/soils.toml")
+        .unwrap();
+    let mut from_csv = SoilRegistry::builtin();
+    from_csv
+        .extend_from_path("tests/fixtures/soil/soils.csv")
+        .unwrap();
+
+    assert_eq!(from_toml.get("alpine_loam"), from_csv.get("alpine_loam"));
+    assert_eq!(from_toml.get("warm_peat"), from_csv.get("warm_peat"));
+
+    assert!(from_toml.extend_from_toml("[bad]\na = 1.0\n").is_err());
+    assert!(from_toml.extend_from_path("soils.json").is_err());
+}
+
+#[test]
+fn test_process_file_with_custom_soil() {
+    let path = "tests/fixtures/data/data_winter.csv".to_string();
+    let builtin = process_file(path.clone(), SoilType::Universal).unwrap();
+
+    let mut registry = SoilRegistry::builtin();
+    registry
+        .extend_from_path("tests/fixtures/soil/soils.csv")
+        .unwrap();
+    let custom = process_file(path, registry.resolve("site_universal").unwrap()).unwrap();
+
+    assert_eq!(builtin.len(), custom.len());
+    for (b, c) in builtin.iter().zip(&custom) {
+        assert!((b.vwc - c.vwc).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn test_default_vwc_params() {
+    let params = VwcParams::default();
+    assert_eq!(params, VwcParams::from(SoilType::Universal));
+    assert_eq!(params.temp_sensor, TempSensor::T1);
+    assert!((params.soil.ref_t - REF_T).abs() < f64::EPSILON);
+    assert!((params.soil.acor_t - ACOR_T).abs() < f64::EPSILON);
+    assert!((params.soil.wcor_t - WCOR_T).abs() < f64::EPSILON);
+
+    // Without temperature the correction constants have no effect
+    let tweaked = SoilParams {
+        acor_t: 2.5,
+        wcor_t: 0.5,
+        ..params.soil
+    };
+    let raw = 2200.0;
+    assert!(
+        (mc_calc_vwc(raw, f64::NAN, tweaked) - mc_calc_vwc(raw, f64::NAN, params)).abs() < 1e-12
+    );
+    assert!((mc_calc_vwc(raw, 5.0, tweaked) - mc_calc_vwc(raw, 5.0, params)).abs() > 1e-4);
+}
+
+#[test]
+fn test_temp_sensor_choice() {
+    let tms = read_tms_file("tests/fixtures/data/data_winter.csv").unwrap();
+    let params = VwcParams {
+        soil: SoilParams::from(SoilType::Loam),
+        temp_sensor: TempSensor::T3,
+    };
+    let records = process_records(&tms, params, &VwcOptions::default());
+
+    for (rec, t) in records.iter().zip(&tms) {
+        assert!((rec.temp - t.t3).abs() < f64::EPSILON);
+        let expected = mc_calc_vwc(t.moisture, t.t3, SoilType::Loam);
+        assert!((rec.vwc - expected).abs() < 1e-12);
+        // Frozen soil is still detected from the soil temperature
+        assert_eq!(rec.frozen, t.t1 < 0.0);
+    }
+    assert_eq!(TempSensor::try_from("t2"), Ok(TempSensor::T2));
+    assert!(TempSensor::try_from("t4").is_err());
+}
@@ -0,0 +1,100 @@
+use chrono::{NaiveDateTime, TimeDelta};
+use soil_sensor_toolbox::{
+    soil_heat_flux, thermal_diffusivity, volumetric_heat_capacity, ThermalOptions, TmsRecord,
+    VwcRecord, DIURNAL_OMEGA,
+};
+
+const DIFFUSIVITY: f64 = 5e-7;
+
+fn assert_close(a: f64, b: f64, tolerance: f64) {
+    assert!((a - b).abs() < tolerance, "{a} != {b}");
+}
+
+/// Three days of 15 minute records of a diurnal wave of 8 °C amplitude
+/// peaking at 14:00 at the surface (T2), damped and delayed at 6 cm (T1)
+/// as in a soil of [`DIFFUSIVITY`]. The third day misses a record.
+fn series() -> Vec<TmsRecord> {
+    let start = NaiveDateTime::parse_from_str("2024-07-01 00:00", "%Y-%m-%d %H:%M").unwrap();
+    let damping_depth = (2.0 * DIFFUSIVITY / DIURNAL_OMEGA).sqrt();
+    let z = 0.06 / damping_depth;
+    (0..288u32)
+        .filter(|i| *i != 250)
+        .map(|i| {
+            let x = DIURNAL_OMEGA * f64::from(i) * 900.0 - DIURNAL_OMEGA * 14.0 * 3600.0;
+            TmsRecord {
+                index: Some(u64::from(i)),
+                datetime: start + TimeDelta::minutes(15 * i64::from(i)),
+                timezone: 4,
+                t1: 15.0 + 8.0 * (-z).exp() * (x - z).cos(),
+                t2: 15.0 + 8.0 * x.cos(),
+                t3: 15.0 + 10.0 * x.cos(),
+                moisture: 2500.0,
+                shake: 0,
+                err_flag: 0,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_thermal_diffusivity() {
+    let days = thermal_diffusivity(&series(), &ThermalOptions::default());
+    // The incomplete third day is skipped
+    assert_eq!(days.len(), 2);
+    for day in &days {
+        assert_close(day.amplitude_surface, 8.0, 1e-9);
+        assert!(day.amplitude_soil < day.amplitude_surface);
+        assert!(day.phase_lag > 0.0 && day.phase_lag < 12.0);
+        assert_close(day.amplitude, DIFFUSIVITY, 1e-12);
+        assert_close(day.phase, DIFFUSIVITY, 1e-12);
+    }
+}
+
+#[test]
+fn test_soil_heat_flux() {
+    let records = series();
+    let vwc: Vec<VwcRecord> = records[..96]
+        .iter()
+        .map(|r| VwcRecord {
+            datetime: r.datetime,
+            timezone: r.timezone,
+            raw: r.moisture,
+            temp: r.t1,
+            vwc: 0.25,
+            frozen: false,
+        })
+        .collect();
+    let options = ThermalOptions::default();
+    let flux = soil_heat_flux(&records, &vwc, &options);
+    assert_eq!(flux.len(), records.len());
+
+    // de Vries: 0.5 · 1.92 + 0.25 · 4.18 MJ/m³/K
+    let capacity = volumetric_heat_capacity(0.25, &options);
+    assert_close(capacity, 2.005e6, 1e-6);
+    let expected = |r: &TmsRecord| DIFFUSIVITY * capacity * (r.t2 - r.t1) / 0.06;
+    assert_close(flux[56].heat_capacity, capacity, 1e-6);
+    assert_close(flux[56].flux, expected(&records[56]), 1e-6);
+    // Warm surface in the afternoon, heat flowing into the soil
+    assert!(flux[56].flux > 0.0);
+    // No VWC after the first day
+    assert!(flux[100].flux.is_nan());
+
+    // A fixed diffusivity applies to the incomplete day as well
+    let options = ThermalOptions {
+        diffusivity: Some(DIFFUSIVITY),
+        ..options
+    };
+    let vwc: Vec<VwcRecord> = records
+        .iter()
+        .map(|r| VwcRecord {
+            datetime: r.datetime,
+            timezone: r.timezone,
+            raw: r.moisture,
+            temp: r.t1,
+            vwc: 0.25,
+            frozen: false,
+        })
+        .collect();
+    let flux = soil_heat_flux(&records, &vwc, &options);
+    assert_close(flux[260].flux, expected(&records[260]), 1e-6);
+}
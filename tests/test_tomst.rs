@@ -0,0 +1,217 @@
+use chrono::NaiveDateTime;
+use soil_sensor_toolbox::{
+    dendro_raw_to_um, parse_utc_offset, read_tms_file, read_tms_file_with_options, read_tomst_file,
+    DecimalSeparator, TmsReadOptions, TmsReader, TmsRecord, TomstDateFormat, TomstLoggerKind,
+    TomstSeries,
+};
+
+fn dt(s: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(s, "%Y.%m.%d %H:%M").unwrap()
+}
+
+#[test]
+fn test_read_all_columns() {
+    let records = read_tms_file("tests/fixtures/data/data.csv").expect("Failed to read data.csv");
+    assert_eq!(records.len(), 43774);
+    assert_eq!(
+        records[0],
+        TmsRecord {
+            index: Some(0),
+            datetime: dt("2023.05.30 06:45"),
+            timezone: 4,
+            t1: 22.25,
+            t2: 22.25,
+            t3: 22.5,
+            moisture: 354.0,
+            shake: 202,
+            err_flag: 0,
+        }
+    );
+    assert_eq!(records.last().unwrap().index, Some(43773));
+}
+
+#[test]
+fn test_missing_values() {
+    let data = "0;2023.05.30 06:45;4;22.25;-200;22.5;354;202;0;\r\n\
+                1;2023.05.30 07:00;4;-200;22;22.125;353;202;1\r\n";
+    let records: Vec<TmsRecord> = TmsReader::new(data.as_bytes())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(records.len(), 2);
+    assert!(records[0].t2.is_nan());
+    assert!(records[1].t1.is_nan());
+    assert!((records[1].t2 - 22.0).abs() < f64::EPSILON);
+    assert_eq!(records[1].err_flag, 1);
+}
+
+#[test]
+fn test_invalid_rows() {
+    let short = "0;2023.05.30 06:45;4;22.25\n";
+    let err = TmsReader::new(short.as_bytes())
+        .next()
+        .unwrap()
+        .unwrap_err();
+    assert!(err.to_string().contains("Line 1"), "{err}");
+
+    let bad_date = "0;30/05/2023;4;22.25;22.25;22.5;354;202;0;\n";
+    assert!(TmsReader::new(bad_date.as_bytes()).next().unwrap().is_err());
+}
+
+#[test]
+fn test_parse_utc_offset() {
+    assert_eq!(parse_utc_offset("UTC").unwrap().local_minus_utc(), 0);
+    assert_eq!(parse_utc_offset("+01:00").unwrap().local_minus_utc(), 3600);
+    assert_eq!(parse_utc_offset("-0230").unwrap().local_minus_utc(), -9000);
+    assert_eq!(parse_utc_offset("+2").unwrap().local_minus_utc(), 7200);
+    assert!(parse_utc_offset("01:00").is_err());
+    assert!(parse_utc_offset("+01:75").is_err());
+    // Not representable as a TOMST time zone code
+    assert!(parse_utc_offset("+00:20").is_err());
+    assert_eq!(parse_utc_offset("+05:45").unwrap().local_minus_utc(), 20700);
+}
+
+#[test]
+fn test_time_zone_conversion() {
+    // Time zone code 4 = UTC+01:00
+    let data = "0;2023.05.30 06:45;4;22.25;22.25;22.5;354;202;0;\n";
+    let as_recorded = TmsReader::new(data.as_bytes()).next().unwrap().unwrap();
+    assert_eq!(as_recorded.utc_offset().local_minus_utc(), 3600);
+    assert_eq!(
+        as_recorded.utc_datetime().naive_utc(),
+        dt("2023.05.30 05:45")
+    );
+
+    let to_utc = TmsReadOptions {
+        output_tz: Some(parse_utc_offset("UTC").unwrap()),
+        ..TmsReadOptions::default()
+    };
+    let rec = TmsReader::with_options(data.as_bytes(), to_utc)
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(rec.datetime, dt("2023.05.30 05:45"));
+    assert_eq!(rec.timezone, 0);
+
+    // Logger clock known to run at UTC+02:00, whatever the file says
+    let overridden = TmsReadOptions {
+        tz_offset: Some(parse_utc_offset("+02:00").unwrap()),
+        output_tz: Some(parse_utc_offset("+01:00").unwrap()),
+        ..TmsReadOptions::default()
+    };
+    let rec = TmsReader::with_options(data.as_bytes(), overridden)
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(rec.datetime, dt("2023.05.30 05:45"));
+    assert_eq!(rec.timezone, 4);
+}
+
+#[test]
+fn test_format_variants() {
+    let reference = read_tms_file("tests/fixtures/tomst/data_ymd_hm.csv").unwrap();
+    assert_eq!(reference.len(), 8);
+
+    for (file, date_format, decimal) in [
+        (
+            "data_ymd_hm.csv",
+            TomstDateFormat::YmdHm,
+            DecimalSeparator::Dot,
+        ),
+        (
+            "data_ymd_hms.csv",
+            TomstDateFormat::YmdHms,
+            DecimalSeparator::Dot,
+        ),
+        (
+            "data_dmy_hm.csv",
+            TomstDateFormat::DmyHm,
+            DecimalSeparator::Dot,
+        ),
+        (
+            "data_dmy_hms.csv",
+            TomstDateFormat::DmyHms,
+            DecimalSeparator::Dot,
+        ),
+        (
+            "data_dmy_hm_comma.csv",
+            TomstDateFormat::DmyHm,
+            DecimalSeparator::Comma,
+        ),
+        (
+            "data_ymd_hms_comma.csv",
+            TomstDateFormat::YmdHms,
+            DecimalSeparator::Comma,
+        ),
+    ] {
+        let path = format!("tests/fixtures/tomst/{file}");
+        let mut reader = TmsReader::from_path(&path).unwrap();
+        let records: Vec<TmsRecord> = reader.by_ref().collect::<Result<_, _>>().unwrap();
+        assert_eq!(records, reference, "{file}");
+        assert_eq!(
+            reader.format(),
+            (Some(date_format), Some(decimal)),
+            "{file}"
+        );
+
+        // An explicit format gives the same records
+        let options = TmsReadOptions {
+            date_format: Some(date_format),
+            decimal: Some(decimal),
+            ..TmsReadOptions::default()
+        };
+        let explicit = read_tms_file_with_options(&path, &options).unwrap();
+        assert_eq!(explicit, reference, "{file}");
+    }
+}
+
+#[test]
+fn test_explicit_format_mismatch() {
+    let options = TmsReadOptions {
+        date_format: Some(TomstDateFormat::YmdHm),
+        ..TmsReadOptions::default()
+    };
+    assert!(read_tms_file_with_options("tests/fixtures/tomst/data_dmy_hm.csv", &options).is_err());
+
+    let options = TmsReadOptions {
+        decimal: Some(DecimalSeparator::Dot),
+        ..TmsReadOptions::default()
+    };
+    assert!(
+        read_tms_file_with_options("tests/fixtures/tomst/data_dmy_hm_comma.csv", &options).is_err()
+    );
+}
+
+#[test]
+fn test_logger_kind_detection() {
+    let options = TmsReadOptions::default();
+    let tms = read_tomst_file("tests/fixtures/tomst/data_ymd_hm.csv", &options).unwrap();
+    assert_eq!(tms.kind(), TomstLoggerKind::Tms);
+    // Damaged T2 and T3 sensors, moisture counts still recorded
+    let damaged = read_tomst_file("tests/fixtures/tomst/data_damaged_t2_t3.csv", &options).unwrap();
+    assert_eq!(damaged.kind(), TomstLoggerKind::Tms);
+
+    let TomstSeries::Thermo(thermo) =
+        read_tomst_file("tests/fixtures/tomst/data_thermo.csv", &options).unwrap()
+    else {
+        panic!("data_thermo.csv not detected as Thermologger");
+    };
+    assert_eq!(thermo.len(), 8);
+    assert!((thermo[0].t - 14.5).abs() < f64::EPSILON);
+
+    let TomstSeries::Dendro(dendro) =
+        read_tomst_file("tests/fixtures/tomst/data_dendro.csv", &options).unwrap()
+    else {
+        panic!("data_dendro.csv not detected as dendrometer");
+    };
+    assert_eq!(dendro.len(), 8);
+    assert!((dendro[0].raw - 12000.0).abs() < f64::EPSILON);
+    assert!((dendro[0].um - dendro_raw_to_um(12000.0)).abs() < f64::EPSILON);
+}
+
+#[test]
+fn test_dendro_conversion() {
+    assert!(dendro_raw_to_um(1279.0).abs() < f64::EPSILON);
+    assert!((dendro_raw_to_um(34000.0) - 8890.0).abs() < 1e-9);
+    // Growth of the stem increases the raw value
+    assert!(dendro_raw_to_um(12003.0) > dendro_raw_to_um(12000.0));
+}
@@ -0,0 +1,41 @@
+use soil_sensor_toolbox::{process_file, FrozenPolicy, SoilType, VwcOptions, VwcReader};
+
+/// Streaming over concatenated files gives the same rows as reading them
+#[test]
+fn test_streaming_reader() {
+    let path = "tests/fixtures/data/data_winter.csv";
+    let expected = process_file(path.to_string(), SoilType::Universal).unwrap();
+
+    let bytes = std::fs::read(path).unwrap();
+    let concatenated = [bytes.as_slice(), bytes.as_slice()].concat();
+    let options = VwcOptions {
+        frozen: FrozenPolicy::Flag,
+        ..VwcOptions::default()
+    };
+    let mut count = 0;
+    for (i, rec) in
+        VwcReader::new(concatenated.as_slice(), SoilType::Universal, options).enumerate()
+    {
+        let rec = rec.unwrap();
+        let exp = &expected[i % expected.len()];
+        assert_eq!(rec.datetime, exp.datetime);
+        assert!((rec.vwc - exp.vwc).abs() < f64::EPSILON);
+        count += 1;
+    }
+    assert_eq!(count, 2 * expected.len());
+
+    // Errors carry the line number and do not stop the stream
+    let broken = b"0;2024.01.10 00:00;4;1.0;1.0;1.0;2000;202;0;\n1;bad;4;1.0;1.0;1.0;2000;202;0;\n2;2024.01.10 00:30;4;1.0;1.0;1.0;2000;202;0;\n";
+    let results: Vec<_> = VwcReader::from_path(path, SoilType::Loam, VwcOptions::default())
+        .unwrap()
+        .take(1)
+        .chain(VwcReader::new(
+            &broken[..],
+            SoilType::Loam,
+            VwcOptions::default(),
+        ))
+        .collect();
+    assert_eq!(results.len(), 4);
+    assert!(results[1].is_ok() && results[3].is_ok());
+    assert!(format!("{:#}", results[2].as_ref().unwrap_err()).contains("Line 2"));
+}